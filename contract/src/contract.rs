@@ -1,7 +1,11 @@
 use crate::error::ContractError;
 use crate::handler::*;
 use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::state::{Config, CONFIG};
+use crate::state::{
+    migrate_denoms, migrate_payment_channels, Config, PauseFlags, VerifyingKeyInfo,
+    ACCEPTED_DENOMS, CONFIG, VERIFYING_KEY,
+};
+use crate::zkp;
 use cosmwasm_std::to_json_binary;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::{
@@ -19,15 +23,38 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
-    let owner = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if !zkp::is_valid_verifying_key(msg.verifying_key.as_slice()) {
+        return Err(ContractError::InvalidVerifyingKey);
+    }
+
+    let owner = match msg.admin {
+        Some(admin) => deps
+            .api
+            .addr_canonicalize(deps.api.addr_validate(&admin)?.as_str())?,
+        None => deps.api.addr_canonicalize(info.sender.as_str())?,
+    };
+    ACCEPTED_DENOMS.save(deps.storage, msg.denom.key(), &msg.denom)?;
     CONFIG.save(deps.storage, &{
         Config {
             denom: msg.denom,
             auto_release_time: msg.auto_release_time,
             max_recipient: msg.max_recipient,
             owner,
+            fee: None,
+            pending_owner: None,
+            paused: PauseFlags::default(),
+            cashing_paused_at: None,
+            cashing_paused_for: 0,
         }
     })?;
+    VERIFYING_KEY.save(
+        deps.storage,
+        &VerifyingKeyInfo {
+            id: 1,
+            key: msg.verifying_key,
+            expires_at: None,
+        },
+    )?;
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
@@ -43,6 +70,21 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     match msg {
         ExecuteMsg::Receive(msg) => handle_cw20_msg(deps, env, info, msg),
+        ExecuteMsg::AddPaymentChan {
+            chan_key,
+            channels,
+            operator,
+        } => add_payment_chan(deps, info, chan_key, channels, operator),
+        ExecuteMsg::TopUp {
+            chan_key,
+            recipient_key,
+        } => top_up(deps, info, chan_key, recipient_key),
+        ExecuteMsg::ReduceChannel {
+            chan_key,
+            recipient_key,
+            amount,
+            commitment,
+        } => reduce_channel(deps, env, info, chan_key, recipient_key, amount, commitment),
         ExecuteMsg::ClosePaymentChan {
             chan_key,
             commitment,
@@ -52,17 +94,56 @@ pub fn execute(
             recipient_key,
             cheques,
         } => cashing(deps, env, info, recipient_key, cheques),
+        ExecuteMsg::SettleAndClose {
+            sender_key,
+            final_cheque,
+        } => settle_and_close(deps, env, info, sender_key, final_cheque),
+        ExecuteMsg::RegisterPayout {
+            recipient_key,
+            address,
+            nonce,
+            commitment,
+        } => register_payout(deps, env, recipient_key, address, nonce, commitment),
         ExecuteMsg::AddSigner {
             chan_key,
             recipient_key,
             signers,
         } => add_signer(deps, info, chan_key, recipient_key, signers),
+        ExecuteMsg::TransferOperator {
+            chan_key,
+            new_operator,
+        } => transfer_operator(deps, info, chan_key, new_operator),
+        ExecuteMsg::AcceptOperator { chan_key } => accept_operator(deps, info, chan_key),
+        ExecuteMsg::RemoveSigner {
+            chan_key,
+            recipient_key,
+            signer,
+            nonce,
+        } => remove_signer(deps, info, chan_key, recipient_key, signer, nonce),
+        ExecuteMsg::ReplaceSigners {
+            chan_key,
+            recipient_key,
+            signers,
+            nonce,
+        } => replace_signers(deps, info, chan_key, recipient_key, signers, nonce),
         ExecuteMsg::UpdateConfig {
-            owner,
             auto_release_time,
             max_recipient,
-        } => update_config(deps, env, info, auto_release_time, owner, max_recipient),
-        _ => panic!("unsupport"),
+            fee,
+        } => update_config(deps, env, info, auto_release_time, max_recipient, fee),
+        ExecuteMsg::ProposeOwner {
+            new_owner,
+            expires_in,
+        } => propose_owner(deps, env, info, new_owner, expires_in),
+        ExecuteMsg::AcceptOwner {} => accept_owner(deps, env, info),
+        ExecuteMsg::CancelOwnerProposal {} => cancel_owner_proposal(deps, env, info),
+        ExecuteMsg::Pause { flags } => set_paused(deps, env, info, flags, true),
+        ExecuteMsg::Unpause { flags } => set_paused(deps, env, info, flags, false),
+        ExecuteMsg::UpdateDenoms { add, remove } => update_denoms(deps, env, info, add, remove),
+        ExecuteMsg::UpdateVerifyingKey {
+            verifying_key,
+            grace_period,
+        } => update_verifying_key(deps, env, info, verifying_key, grace_period),
     }
 }
 
@@ -72,22 +153,43 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::PaymentChan {
             sender_pubkey_hash,
             recipient_pubkey_hash,
-            page,
-            size,
+            start_after,
+            limit,
         } => to_json_binary(&payment_chan(
             deps,
             env,
             sender_pubkey_hash,
             recipient_pubkey_hash,
-            page,
-            size,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ChannelsByRecipient {
+            recipient_key,
+            start_after,
+            limit,
+        } => to_json_binary(&channels_by_recipient(
+            deps,
+            recipient_key,
+            start_after,
+            limit,
         )?),
+        QueryMsg::Payout {
+            recipient_pubkey_hash,
+        } => to_json_binary(&payout(deps, recipient_pubkey_hash)?),
+        QueryMsg::RecipientCount { sender_pubkey_hash } => {
+            to_json_binary(&recipient_count(deps, sender_pubkey_hash)?)
+        }
+        QueryMsg::AcceptedDenoms {} => to_json_binary(&accepted_denoms(deps)?),
         QueryMsg::Config {} => to_json_binary(&config(deps)?),
+        QueryMsg::VerifyingKey {} => to_json_binary(&verifying_key(deps, env)?),
+        QueryMsg::FeeEstimate { denom, amount } => {
+            to_json_binary(&fee_estimate(deps, denom, amount)?)
+        }
     }
 }
 
 #[entry_point]
-pub fn migrate(deps: DepsMut, _env: Env, _: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
     let ver = get_contract_version(deps.storage)?;
     // ensure we are migrating from an allowed contract
     if ver.contract != CONTRACT_NAME {
@@ -98,6 +200,27 @@ pub fn migrate(deps: DepsMut, _env: Env, _: MigrateMsg) -> Result<Response, Cont
         return Err(StdError::generic_err("Cannot upgrade from a newer version").into());
     }
 
+    // deployments predating proof verification have no key to verify commitments with
+    if !VERIFYING_KEY.exists(deps.storage) {
+        let verifying_key = msg
+            .verifying_key
+            .ok_or(ContractError::MissingVerifyingKey)?;
+        if !zkp::is_valid_verifying_key(verifying_key.as_slice()) {
+            return Err(ContractError::InvalidVerifyingKey);
+        }
+        VERIFYING_KEY.save(
+            deps.storage,
+            &VerifyingKeyInfo {
+                id: 1,
+                key: verifying_key,
+                expires_at: None,
+            },
+        )?;
+    }
+
+    migrate_denoms(deps.storage)?;
+    migrate_payment_channels(deps.storage)?;
+
     // set the new version
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
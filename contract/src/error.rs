@@ -1,14 +1,18 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{OverflowError, StdError};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
     #[error("unsupport denom")]
     UnsupportDenom(),
     #[error("unsupport msg")]
     UnsupportMsg,
+    #[error("Payment channel is funded in {denom}")]
+    DenomMismatch { denom: String },
     #[error("Insufficient funds")]
     InsufficientFund,
     #[error("Exceed max recipient num")]
@@ -17,4 +21,62 @@ pub enum ContractError {
     NotOwner { sender: String, owner: String },
     #[error("ErrChecks: Verify checks failed")]
     ChecksVerifyFailed,
+    #[error("Unauthorized cw20 contract {sender}")]
+    UnauthorizedCw20 { sender: String },
+    #[error("NotOperator: Sender is {sender}, but operator is {operator}.")]
+    NotOperator { sender: String, operator: String },
+    #[error("Sender {sender} is not the pending operator")]
+    NotPendingOperator { sender: String },
+    #[error("Sender {sender} is not the pending owner")]
+    NotPendingOwner { sender: String },
+    #[error("Ownership proposal expired at {expires_at}")]
+    OwnerProposalExpired { expires_at: u64 },
+    #[error("No pending ownership proposal")]
+    NoPendingOwner,
+    #[error("Key {key} is not a canonical pubkey hash")]
+    InvalidKey { key: String },
+    #[error("Payment channel not found")]
+    ChannelNotFound,
+    #[error("Recipient not found")]
+    RecipientNotFound,
+    #[error("Cheque nonce must be greater than the withdrawn nonce")]
+    NonceNotIncreasing,
+    #[error("Cheque value does not match the channel payment type")]
+    InvalidChequeValue,
+    #[error("Cheque value exceeds the channel balance")]
+    Overdraw,
+    #[error("Signer {signer_key} is not approved for the channel")]
+    UnapprovedSigner { signer_key: String },
+    #[error("Signer {signer_key} not found")]
+    SignerNotFound { signer_key: String },
+    #[error("Exceed max signer num")]
+    ExceedSignerNum,
+    #[error("Exceed max revoked signer num")]
+    ExceedRevokedSignerNum,
+    #[error("Duplicate cheque of sender {sender_key} in one batch")]
+    DuplicateCheque { sender_key: String },
+    #[error("No payout address registered for the recipient")]
+    PayoutNotRegistered,
+    #[error("Sender {sender} is not the payout address of the recipient")]
+    NotPayoutAddress { sender: String },
+    #[error("Face value of recipient {recipient_key} can not be changed")]
+    FaceValueMismatch { recipient_key: String },
+    #[error("Channel is not open")]
+    ChannelNotOpen,
+    #[error("Channel is already settled")]
+    ChannelSettled,
+    #[error("Channel is in its challenge period until {deadline}")]
+    ChallengePeriod { deadline: u64 },
+    #[error("Reduce amount must be positive and within the channel balance")]
+    InvalidReduceAmount,
+    #[error("Reduction is in its notice period until {available_at}")]
+    NoticePeriod { available_at: u64 },
+    #[error("Fee rate can not exceed {max_bps} bps")]
+    InvalidFee { max_bps: u16 },
+    #[error("{operation} paused")]
+    Paused { operation: String },
+    #[error("Invalid verifying key")]
+    InvalidVerifyingKey,
+    #[error("Migration requires a verifying key")]
+    MissingVerifyingKey,
 }
@@ -1,29 +1,108 @@
 use cosmwasm_std::{
-    from_json, to_json_binary, BankMsg, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, Response, StdResult, SubMsg, Uint128, WasmMsg,
+    from_json, to_json_binary, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
+    Event, MessageInfo, Order, Response, StdResult, Storage, SubMsg, Uint128, Uint64, WasmMsg,
 };
 
 use crate::{
     error::ContractError,
     msg::*,
-    state::{Config, Denom, PaymentChannel, Recipient, CONFIG, PAYMENT_CHANNELS},
+    state::{
+        recipients, ChannelState, Config, Denom, Fee, PauseFlags, PaymentChannel, Payout,
+        PendingOwner, Recipient, Reduction, VerifyingKeyInfo, ACCEPTED_DENOMS, CONFIG, MAX_FEE_BPS,
+        PAYMENT_CHANNELS, PAYOUTS, RETIRED_VERIFYING_KEYS, VERIFYING_KEY,
+    },
+    zkp,
 };
 use cw20::Cw20ReceiveMsg;
-use std::collections::HashMap;
+use cw_storage_plus::Bound;
+use std::collections::HashSet;
 
 use cosmwasm_tools::access_ctrl as constraints;
 
+fn load_payment_chan(
+    storage: &dyn Storage,
+    sender_pubkey_hash: &str,
+) -> Result<PaymentChannel, ContractError> {
+    PAYMENT_CHANNELS
+        .may_load(storage, sender_pubkey_hash)?
+        .ok_or(ContractError::ChannelNotFound)
+}
+
+fn load_recipient(
+    storage: &dyn Storage,
+    sender_pubkey_hash: &str,
+    recipient_pubkey_hash: &str,
+) -> Result<Recipient, ContractError> {
+    recipients()
+        .may_load(storage, (sender_pubkey_hash, recipient_pubkey_hash))?
+        .ok_or(ContractError::RecipientNotFound)
+}
+
+/// Event of a recipient channel state transition
+fn channel_event(ty: &str, sender_pubkey_hash: &str, recipient_pubkey_hash: &str) -> Event {
+    Event::new(ty)
+        .add_attribute("sender_key", sender_pubkey_hash)
+        .add_attribute("recipient_key", recipient_pubkey_hash)
+}
+
+/// Upper bound of the approved signers of a recipient channel
+const MAX_SIGNERS: usize = 16;
+/// Upper bound of the revoked signers of a recipient channel, blocked ones included
+const MAX_REVOKED_SIGNERS: usize = 64;
+
+/// End of a challenge period set to end at `deadline` when cashing had been paused for
+/// `paused_for` seconds. Recipients can not cash while cashing is paused, so the period only runs
+/// while it is not.
+fn challenge_end(
+    cfg: &Config,
+    now: u64,
+    deadline: u64,
+    paused_for: u64,
+) -> Result<u64, ContractError> {
+    let paused = Uint64::new(cfg.cashing_paused_time(now)?).checked_sub(Uint64::new(paused_for))?;
+    add_seconds(deadline, paused.u64())
+}
+
+/// Block time `seconds` after `now`, failing instead of overflowing on huge durations
+fn add_seconds(now: u64, seconds: u64) -> Result<u64, ContractError> {
+    Ok(Uint64::new(now).checked_add(Uint64::new(seconds))?.u64())
+}
+
+/// Reject keys the circuit can not prove for. A channel opened under one could never be closed
+/// by its sender, nor cashed by its recipient.
+fn check_keys<'a>(keys: impl IntoIterator<Item = &'a String>) -> Result<(), ContractError> {
+    for key in keys {
+        if zkp::key_to_field(key).is_none() {
+            return Err(ContractError::InvalidKey { key: key.clone() });
+        }
+    }
+
+    Ok(())
+}
+
+fn operator_only(payment_chan: &PaymentChannel, sender: &str) -> Result<(), ContractError> {
+    if payment_chan.operator != sender {
+        return Err(ContractError::NotOperator {
+            sender: sender.to_string(),
+            operator: payment_chan.operator.clone(),
+        });
+    }
+
+    Ok(())
+}
+
 pub fn handle_cw20_msg(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
     msg: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
-    match config.denom {
-        Denom::Native(_) => return Err(ContractError::UnsupportDenom()),
-        Denom::Cw20(addr) => assert_eq!(addr, info.sender),
-    };
+    let denom = Denom::Cw20(info.sender.clone());
+    if !ACCEPTED_DENOMS.has(deps.storage, denom.key()) {
+        return Err(ContractError::UnauthorizedCw20 {
+            sender: info.sender.to_string(),
+        });
+    }
 
     let raw: ExecuteMsg = from_json(&msg.msg)?;
     match raw {
@@ -31,62 +110,249 @@ pub fn handle_cw20_msg(
             chan_key: sender_pubkey_hash,
             channels,
             operator,
-        } => build_payment_chan(
-            deps,
-            env,
-            msg,
-            sender_pubkey_hash,
-            channels,
-            operator.unwrap_or(info.sender.to_string()),
-        ),
-        _ => unreachable!(),
+        } => {
+            // the token holder funding the channel, not the cw20 contract, operates it by default
+            let operator = operator.unwrap_or(msg.sender.clone());
+            build_payment_chan(
+                deps,
+                msg.sender,
+                denom,
+                msg.amount,
+                sender_pubkey_hash,
+                channels,
+                operator,
+            )
+        }
+        ExecuteMsg::TopUp {
+            chan_key: sender_pubkey_hash,
+            recipient_key,
+        } => build_top_up(deps, denom, msg.amount, sender_pubkey_hash, recipient_key),
+        _ => Err(ContractError::UnsupportMsg),
     }
 }
 
+/// Denom and sum of the funds sent along the message, which must all be in one accepted native
+/// denom
+fn native_funds(
+    storage: &dyn Storage,
+    info: &MessageInfo,
+) -> Result<(Denom, Uint128), ContractError> {
+    let Some(first) = info.funds.first() else {
+        return Err(ContractError::InsufficientFund);
+    };
+    let denom = Denom::Native(first.denom.clone());
+    if !ACCEPTED_DENOMS.has(storage, denom.key()) {
+        return Err(ContractError::UnsupportDenom());
+    }
+
+    let mut amount = Uint128::zero();
+    for coin in info.funds.iter() {
+        if coin.denom != first.denom {
+            return Err(ContractError::UnsupportDenom());
+        }
+        amount = amount.checked_add(coin.amount)?;
+    }
+
+    Ok((denom, amount))
+}
+
+/// Fund payment channels with the native denom sent along the message
+pub fn add_payment_chan(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    channels: Vec<Channel>,
+    operator: Option<String>,
+) -> Result<Response, ContractError> {
+    let (denom, amount) = native_funds(deps.storage, &info)?;
+
+    build_payment_chan(
+        deps,
+        info.sender.to_string(),
+        denom,
+        amount,
+        sender_pubkey_hash,
+        channels,
+        operator.unwrap_or(info.sender.to_string()),
+    )
+}
+
+/// Add `channels` to the payment channel of `sender_pubkey_hash`. The part of `amount` exceeding
+/// the channels total is refunded to `funder`, which must be the operator of an existing payment
+/// channel.
+#[constraints(not_paused(deps.as_ref(), Pausable::Deposits))]
 pub fn build_payment_chan(
     deps: DepsMut,
-    _env: Env,
-    msg: Cw20ReceiveMsg,
+    funder: String,
+    denom: Denom,
+    amount: Uint128,
     sender_pubkey_hash: String,
     channels: Vec<Channel>, // recipient_pubkey_hash, face_value, total
     operator: String,
 ) -> Result<Response, ContractError> {
-    let mut total_amt = 0;
+    let cfg = CONFIG.load(deps.storage)?;
+    check_keys([&sender_pubkey_hash])?;
+    let mut total_amt = Uint128::zero();
     for chan in channels.iter() {
-        total_amt += chan.max_amount;
+        check_keys([&chan.key].into_iter().chain(chan.approve_signers.iter()))?;
+        total_amt = total_amt.checked_add(Uint128::new(chan.max_amount))?;
     }
-    if msg.amount.lt(&Uint128::from(total_amt)) {
+    if amount.lt(&total_amt) {
         return Err(ContractError::InsufficientFund);
     }
     // create a new one if not exist for the given key
-    let mut payment_chan = PAYMENT_CHANNELS
-        .may_load(deps.storage, sender_pubkey_hash.clone())?
-        .unwrap_or(PaymentChannel {
-            operator: operator.clone(),
-            recipients: HashMap::new(),
+    let mut payment_chan = match PAYMENT_CHANNELS.may_load(deps.storage, &sender_pubkey_hash)? {
+        // checked against the actual caller, `operator` is only a parameter of new channels
+        Some(payment_chan) => {
+            operator_only(&payment_chan, &funder)?;
+            payment_chan
+        }
+        // refunds and every management call depend on the operator, it must be a valid address
+        None => PaymentChannel {
+            operator: deps.api.addr_validate(&operator)?.to_string(),
+            recipient_count: 0,
+            denom: denom.clone(),
+            pending_operator: None,
+            closes: 0,
+        },
+    };
+    // all recipient channels of a sender share its denom
+    if payment_chan.denom != denom {
+        return Err(ContractError::DenomMismatch {
+            denom: payment_chan.denom.name(),
         });
+    }
 
-    assert_eq!(payment_chan.operator, operator);
-
+    let mut events = vec![];
     for chan in channels {
-        let recipient = payment_chan.recipients.get_mut(chan.key.as_str());
-        if let Some(r) = recipient {
-            r.max_amount += chan.max_amount;
-        } else {
-            payment_chan.recipients.insert(
-                chan.key.clone(),
-                Recipient::new(
-                    chan.approve_signers,
-                    chan.max_amount,
-                    chan.face_value.unwrap(),
-                ),
-            );
-        }
+        let key = (sender_pubkey_hash.as_str(), chan.key.as_str());
+        let (event, recipient) = match recipients().may_load(deps.storage, key)? {
+            Some(mut r) => {
+                let event = match r.state {
+                    ChannelState::Open => "channel_topped_up",
+                    ChannelState::Closing { .. } => return Err(ContractError::ChannelNotOpen),
+                    // funding a settled channel opens it again, its nonce carries over
+                    ChannelState::Settled => {
+                        r.state = ChannelState::Open;
+                        payment_chan.add_recipient()?;
+                        "channel_opened"
+                    }
+                };
+                // cheques already signed must keep their price
+                if r.face_value != chan.face_value {
+                    return Err(ContractError::FaceValueMismatch {
+                        recipient_key: chan.key.clone(),
+                    });
+                }
+                r.max_amount = Uint128::new(r.max_amount)
+                    .checked_add(Uint128::new(chan.max_amount))?
+                    .u128();
+                r.approve(chan.approve_signers);
+                (event, r)
+            }
+            None => {
+                payment_chan.add_recipient()?;
+                let r = Recipient::new(chan.approve_signers, chan.max_amount, chan.face_value);
+                ("channel_opened", r)
+            }
+        };
+        check_signer_num(&recipient)?;
+        recipients().save(deps.storage, key, &recipient)?;
+
+        events.push(
+            channel_event(event, key.0, key.1)
+                .add_attribute("amount", chan.max_amount.to_string())
+                .add_attribute("max_amount", recipient.max_amount.to_string())
+                .add_attribute(
+                    "face_value",
+                    recipient
+                        .face_value
+                        .map_or("any".to_string(), |v| v.to_string()),
+                )
+                .add_attribute("operator", &payment_chan.operator),
+        );
     }
 
-    PAYMENT_CHANNELS.save(deps.storage, sender_pubkey_hash, &payment_chan)?;
+    if payment_chan.recipient_count > cfg.max_recipient {
+        return Err(ContractError::ExceedRecipientNum);
+    }
+
+    PAYMENT_CHANNELS.save(deps.storage, &sender_pubkey_hash, &payment_chan)?;
+
+    let surplus = amount.checked_sub(total_amt)?;
+    let sub_msgs = if !surplus.is_zero() {
+        build_transfer_msg(&denom, funder, surplus.u128())?
+    } else {
+        vec![]
+    };
+
+    Ok(Response::new()
+        .add_attribute("method", "add_payment")
+        .add_events(events)
+        .add_submessages(sub_msgs))
+}
+
+/// Add the native funds sent along the message to an existing recipient channel
+pub fn top_up(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    recipient_pubkey_hash: String,
+) -> Result<Response, ContractError> {
+    let (denom, amount) = native_funds(deps.storage, &info)?;
 
-    Ok(Response::new().add_attribute("method", "add_payment"))
+    build_top_up(
+        deps,
+        denom,
+        amount,
+        sender_pubkey_hash,
+        recipient_pubkey_hash,
+    )
+}
+
+/// Raise the balance of a recipient channel by `amount`, keeping its face value and signers
+#[constraints(not_paused(deps.as_ref(), Pausable::Deposits))]
+pub fn build_top_up(
+    deps: DepsMut,
+    denom: Denom,
+    amount: Uint128,
+    sender_pubkey_hash: String,
+    recipient_pubkey_hash: String,
+) -> Result<Response, ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::InsufficientFund);
+    }
+
+    let payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+    if payment_chan.denom != denom {
+        return Err(ContractError::DenomMismatch {
+            denom: payment_chan.denom.name(),
+        });
+    }
+
+    let mut recipient = load_recipient(deps.storage, &sender_pubkey_hash, &recipient_pubkey_hash)?;
+    if recipient.state != ChannelState::Open {
+        return Err(ContractError::ChannelNotOpen);
+    }
+    recipient.max_amount = Uint128::new(recipient.max_amount)
+        .checked_add(amount)?
+        .u128();
+
+    recipients().save(
+        deps.storage,
+        (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str()),
+        &recipient,
+    )?;
+
+    Ok(Response::new().add_attribute("method", "top_up").add_event(
+        channel_event(
+            "channel_topped_up",
+            &sender_pubkey_hash,
+            &recipient_pubkey_hash,
+        )
+        .add_attribute("amount", amount)
+        .add_attribute("max_amount", recipient.max_amount.to_string()),
+    ))
 }
 
 pub fn add_signer(
@@ -94,22 +360,186 @@ pub fn add_signer(
     info: MessageInfo,
     sender_pubkey_hash: String,
     recipient_pubkey_hash: String,
-    mut signers: Vec<String>,
+    signers: Vec<String>,
 ) -> Result<Response, ContractError> {
-    let mut payment_chan = PAYMENT_CHANNELS.load(deps.storage, sender_pubkey_hash.clone())?;
+    let payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
 
-    assert_eq!(payment_chan.operator, info.sender.to_string());
+    operator_only(&payment_chan, info.sender.as_str())?;
 
-    let recipient = payment_chan
-        .recipients
-        .get_mut(&recipient_pubkey_hash)
-        .unwrap();
+    let mut recipient = load_recipient(deps.storage, &sender_pubkey_hash, &recipient_pubkey_hash)?;
+    check_keys(&signers)?;
 
-    recipient.approve_signers.append(&mut signers);
+    let event = channel_event("signer_added", &sender_pubkey_hash, &recipient_pubkey_hash)
+        .add_attribute("signers", signers.join(","));
+    recipient.reinstate(signers);
+    check_signer_num(&recipient)?;
 
-    PAYMENT_CHANNELS.save(deps.storage, sender_pubkey_hash, &payment_chan)?;
+    recipients().save(
+        deps.storage,
+        (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str()),
+        &recipient,
+    )?;
 
-    Ok(Response::new().add_attribute("method", "add_signer"))
+    Ok(Response::new()
+        .add_attribute("method", "add_signer")
+        .add_event(event))
+}
+
+/// Propose `new_operator` for the payment channel of `sender_pubkey_hash`. The current operator
+/// stays in charge until the proposed one accepts.
+pub fn transfer_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    new_operator: String,
+) -> Result<Response, ContractError> {
+    let mut payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    operator_only(&payment_chan, info.sender.as_str())?;
+
+    let new_operator = deps.api.addr_validate(&new_operator)?;
+    payment_chan.pending_operator = Some(new_operator.to_string());
+
+    PAYMENT_CHANNELS.save(deps.storage, &sender_pubkey_hash, &payment_chan)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "transfer_operator")
+        .add_event(
+            Event::new("operator_transfer_proposed")
+                .add_attribute("sender_key", sender_pubkey_hash)
+                .add_attribute("operator", payment_chan.operator)
+                .add_attribute("new_operator", new_operator),
+        ))
+}
+
+pub fn accept_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+) -> Result<Response, ContractError> {
+    let mut payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    if payment_chan.pending_operator.as_deref() != Some(info.sender.as_str()) {
+        return Err(ContractError::NotPendingOperator {
+            sender: info.sender.to_string(),
+        });
+    }
+
+    let old_operator = std::mem::replace(&mut payment_chan.operator, info.sender.to_string());
+    payment_chan.pending_operator = None;
+
+    PAYMENT_CHANNELS.save(deps.storage, &sender_pubkey_hash, &payment_chan)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "accept_operator")
+        .add_event(
+            Event::new("operator_transferred")
+                .add_attribute("sender_key", sender_pubkey_hash)
+                .add_attribute("old_operator", old_operator)
+                .add_attribute("operator", payment_chan.operator),
+        ))
+}
+
+/// Revoke `signer`. Its cheques up to `nonce`, the withdrawn nonce by default, stay valid.
+pub fn remove_signer(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    recipient_pubkey_hash: String,
+    signer: String,
+    nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    operator_only(&payment_chan, info.sender.as_str())?;
+
+    let mut recipient = load_recipient(deps.storage, &sender_pubkey_hash, &recipient_pubkey_hash)?;
+
+    let nonce = nonce.unwrap_or(recipient.nonce_withdrawl.unwrap_or(0));
+    if !recipient.revoke(&signer, nonce) {
+        return Err(ContractError::SignerNotFound { signer_key: signer });
+    }
+    check_signer_num(&recipient)?;
+
+    recipients().save(
+        deps.storage,
+        (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str()),
+        &recipient,
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "remove_signer")
+        .add_event(
+            channel_event(
+                "signer_removed",
+                &sender_pubkey_hash,
+                &recipient_pubkey_hash,
+            )
+            .add_attribute("signers", signer)
+            .add_attribute("nonce", nonce.to_string()),
+        ))
+}
+
+/// Replace the approved signers with `signers`, revoking the dropped ones as in `remove_signer`
+pub fn replace_signers(
+    deps: DepsMut,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    recipient_pubkey_hash: String,
+    signers: Vec<String>,
+    nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    operator_only(&payment_chan, info.sender.as_str())?;
+
+    let mut recipient = load_recipient(deps.storage, &sender_pubkey_hash, &recipient_pubkey_hash)?;
+    check_keys(&signers)?;
+
+    let nonce = nonce.unwrap_or(recipient.nonce_withdrawl.unwrap_or(0));
+    let dropped = recipient
+        .approve_signers
+        .iter()
+        .filter(|signer| !signers.contains(signer))
+        .cloned()
+        .collect::<Vec<_>>();
+    for signer in dropped.iter() {
+        recipient.revoke(signer, nonce);
+    }
+    let added = channel_event("signer_added", &sender_pubkey_hash, &recipient_pubkey_hash)
+        .add_attribute("signers", signers.join(","));
+    recipient.reinstate(signers);
+    check_signer_num(&recipient)?;
+
+    recipients().save(
+        deps.storage,
+        (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str()),
+        &recipient,
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "replace_signers")
+        .add_event(
+            channel_event(
+                "signer_removed",
+                &sender_pubkey_hash,
+                &recipient_pubkey_hash,
+            )
+            .add_attribute("signers", dropped.join(","))
+            .add_attribute("nonce", nonce.to_string()),
+        )
+        .add_event(added))
+}
+
+fn check_signer_num(recipient: &Recipient) -> Result<(), ContractError> {
+    if recipient.approve_signers.len() > MAX_SIGNERS {
+        return Err(ContractError::ExceedSignerNum);
+    }
+    if recipient.revoked_signers.len() + recipient.blocked_signers.len() > MAX_REVOKED_SIGNERS {
+        return Err(ContractError::ExceedRevokedSignerNum);
+    }
+
+    Ok(())
 }
 
 pub fn close_payment(
@@ -118,60 +548,122 @@ pub fn close_payment(
     info: MessageInfo,
     sender_pubkey_hash: String,
     sender_commitment: Vec<u8>,
-    recipients: Vec<(String, Vec<u8>)>, // recipient_pubkey_hash, recipient_commitment
+    channels: Vec<(String, Vec<u8>)>, // recipient_pubkey_hash, recipient_commitment
 ) -> Result<Response, ContractError> {
+    let mut payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    operator_only(&payment_chan, info.sender.as_str())?;
+
+    // a close request of the sender is single use, whoever operates the channel
     verify_commitment(
+        deps.as_ref(),
+        &env,
         &sender_pubkey_hash,
-        CommitmentType::CloseChannel,
-        sender_commitment,
+        CommitmentType::CloseChannel {
+            sender_key: &sender_pubkey_hash,
+            closes: payment_chan.closes,
+        },
+        &sender_commitment,
     )?;
+    payment_chan.closes = Uint64::new(payment_chan.closes)
+        .checked_add(Uint64::new(1))?
+        .u64();
 
     let cfg = CONFIG.load(deps.storage)?;
 
-    let mut payment_chan = PAYMENT_CHANNELS.load(deps.storage, sender_pubkey_hash.clone())?;
-
-    assert_eq!(payment_chan.operator, info.sender.to_string());
-
-    let mut refund_amt = 0;
-    for (addr, commitment) in recipients {
-        let recipient = payment_chan.recipients.get_mut(addr.as_str());
-        if recipient.is_none() {
+    let now = env.block.time.seconds();
+    let mut refund_amt = Uint128::zero();
+    let mut events = vec![];
+    for (recipient_pubkey_hash, commitment) in channels {
+        let key = (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str());
+        let Some(mut r) = recipients().may_load(deps.storage, key)? else {
+            continue;
+        };
+        if r.state == ChannelState::Settled {
             continue;
         }
 
-        if let Some(r) = recipient {
-            if verify_commitment(&addr, CommitmentType::CloseChannel, commitment).is_ok() {
-                // settlement
-                refund_amt += r.remain();
-                payment_chan.recipients.remove(addr.as_str());
-            } else {
-                // auto release
-                if let Some(auto_release) = r.auto_release {
-                    if auto_release <= env.block.time.seconds() {
-                        refund_amt += r.remain()
-                    }
+        // the recipient consent skips the challenge period, unless closing is paused so that
+        // senders can still recover their funds once it ends
+        let withdrawn = r.withdrawn()?;
+        let cooperative = !cfg.paused.closing
+            && verify_commitment(
+                deps.as_ref(),
+                &env,
+                &recipient_pubkey_hash,
+                CommitmentType::CloseConsent {
+                    sender_key: &sender_pubkey_hash,
+                    recipient_key: &recipient_pubkey_hash,
+                    epoch: r.epoch,
+                    withdrawn,
+                },
+                &commitment,
+            )
+            .is_ok();
 
-                    payment_chan.recipients.remove(addr.as_str());
-                } else {
-                    r.auto_release = Some(env.block.time.seconds() + cfg.auto_release_time);
+        match r.state {
+            ChannelState::Closing {
+                deadline,
+                paused_for,
+            } if !cooperative => {
+                let deadline = challenge_end(&cfg, now, deadline, paused_for)?;
+                if now < deadline {
+                    return Err(ContractError::ChallengePeriod { deadline });
                 }
             }
+            _ => {}
         }
+
+        match r.state {
+            ChannelState::Open if !cooperative => {
+                let deadline = add_seconds(now, cfg.auto_release_time)?;
+                r.state = ChannelState::Closing {
+                    deadline,
+                    paused_for: cfg.cashing_paused_time(now)?,
+                };
+                events.push(
+                    channel_event("channel_closing", key.0, key.1)
+                        .add_attribute("deadline", deadline.to_string()),
+                );
+            }
+            _ => {
+                let refund = r.settle()?;
+                refund_amt = refund_amt.checked_add(Uint128::new(refund))?;
+                payment_chan.remove_recipient()?;
+                events.push(
+                    channel_event("channel_closed", key.0, key.1)
+                        .add_attribute("refund", refund.to_string())
+                        .add_attribute("cooperative", cooperative.to_string()),
+                );
+            }
+        }
+        recipients().save(deps.storage, key, &r)?;
     }
 
-    // make refund
-    let sub_msg = build_transfer_msg(&cfg, info.sender.to_string(), refund_amt)?;
+    PAYMENT_CHANNELS.save(deps.storage, &sender_pubkey_hash, &payment_chan)?;
 
-    PAYMENT_CHANNELS.save(deps.storage, sender_pubkey_hash, &payment_chan)?;
+    // make refund
+    let sub_msgs = if !refund_amt.is_zero() {
+        build_transfer_msg(
+            &payment_chan.denom,
+            info.sender.to_string(),
+            refund_amt.u128(),
+        )?
+    } else {
+        vec![]
+    };
 
-    Ok(Response::new().add_submessages(sub_msg))
+    Ok(Response::new()
+        .add_attribute("method", "close_payment")
+        .add_events(events)
+        .add_submessages(sub_msgs))
 }
 
 use cw20::Cw20ExecuteMsg;
 
-fn build_transfer_msg(cfg: &Config, to: String, amt: u128) -> Result<Vec<SubMsg>, ContractError> {
+fn build_transfer_msg(denom: &Denom, to: String, amt: u128) -> Result<Vec<SubMsg>, ContractError> {
     let mut res = vec![];
-    match cfg.denom.clone() {
+    match denom.clone() {
         Denom::Native(denom) => {
             res.push(SubMsg::new(BankMsg::Send {
                 to_address: to,
@@ -199,61 +691,555 @@ fn build_transfer_msg(cfg: &Config, to: String, amt: u128) -> Result<Vec<SubMsg>
     Ok(res)
 }
 
+#[constraints(not_paused(deps.as_ref(), Pausable::Cashing))]
 pub fn cashing(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    recipient_pubkey_hash: String,
+    cheques: Vec<PaymentCheque>,
+) -> Result<Response, ContractError> {
+    let (total_cash, events) = cash_cheques(deps.branch(), &env, &recipient_pubkey_hash, cheques)?;
+
+    let payout = load_payout(deps.storage, &recipient_pubkey_hash)?;
+    let fee = CONFIG.load(deps.storage)?.fee;
+
+    // funds always go to the address bound to the recipient key, whoever submits the cheques
+    let mut sub_msgs = vec![];
+    let mut fee_events = vec![];
+    for (denom, cash) in total_cash {
+        let (msgs, event) = build_payout_msgs(
+            fee.as_ref(),
+            &denom,
+            payout.address.to_string(),
+            cash.u128(),
+        )?;
+        sub_msgs.extend(msgs);
+        fee_events.extend(event);
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "cashing")
+        .add_events(events)
+        .add_events(fee_events)
+        .add_submessages(sub_msgs))
+}
+
+/// Pay `cash` to `to` after deducting the protocol fee, which is sent to the fee collector
+fn build_payout_msgs(
+    fee: Option<&Fee>,
+    denom: &Denom,
+    to: String,
+    cash: u128,
+) -> Result<(Vec<SubMsg>, Option<Event>), ContractError> {
+    let fee_amt = fee.map_or(0, |fee| fee.compute(denom, cash));
+
+    let mut res = vec![];
+    if cash > fee_amt {
+        res.extend(build_transfer_msg(denom, to, cash - fee_amt)?);
+    }
+
+    let event = match fee {
+        Some(fee) if fee_amt > 0 => {
+            res.extend(build_transfer_msg(
+                denom,
+                fee.collector.to_string(),
+                fee_amt,
+            )?);
+            Some(
+                Event::new("fee_collected")
+                    .add_attribute("denom", denom.name())
+                    .add_attribute("amount", fee_amt.to_string())
+                    .add_attribute("collector", fee.collector.to_string()),
+            )
+        }
+        _ => None,
+    };
+
+    Ok((res, event))
+}
+
+/// Lower the balance of a recipient channel by `amount` and refund it to the operator. Without
+/// the recipient consent the reduction is announced first and applied by a second call once the
+/// notice period has passed, leaving the recipient time to cash its cheques.
+pub fn reduce_channel(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
+    sender_pubkey_hash: String,
     recipient_pubkey_hash: String,
-    cheques: Vec<PaymentCheque>,
+    amount: u128,
+    commitment: Option<Vec<u8>>,
 ) -> Result<Response, ContractError> {
-    payment_check_interval_verify(deps.as_ref(), &cheques)?;
+    let payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+
+    operator_only(&payment_chan, info.sender.as_str())?;
+
+    let mut recipient = load_recipient(deps.storage, &sender_pubkey_hash, &recipient_pubkey_hash)?;
+    if recipient.state != ChannelState::Open {
+        return Err(ContractError::ChannelNotOpen);
+    }
+    if amount == 0 || amount > recipient.remain()? {
+        return Err(ContractError::InvalidReduceAmount);
+    }
 
     let cfg = CONFIG.load(deps.storage)?;
-    let mut total_cash = 0;
+    let now = env.block.time.seconds();
+    // while closing is paused reductions go through the notice period
+    let consent = match commitment.filter(|_| !cfg.paused.closing) {
+        Some(commitment) => {
+            verify_commitment(
+                deps.as_ref(),
+                &env,
+                &recipient_pubkey_hash,
+                CommitmentType::ReduceChannel {
+                    sender_key: &sender_pubkey_hash,
+                    recipient_key: &recipient_pubkey_hash,
+                    max_amount: recipient.max_amount,
+                    amount,
+                    reductions: recipient.reductions,
+                },
+                &commitment,
+            )?;
+            true
+        }
+        None => false,
+    };
+
+    let key = (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str());
+    if !consent {
+        match recipient.reduction.as_ref().filter(|r| r.amount == amount) {
+            Some(reduction) => {
+                let available_at =
+                    challenge_end(&cfg, now, reduction.available_at, reduction.paused_for)?;
+                if now < available_at {
+                    return Err(ContractError::NoticePeriod { available_at });
+                }
+            }
+            // a new announcement replaces the pending one
+            None => {
+                let available_at = add_seconds(now, cfg.auto_release_time)?;
+                recipient.reduction = Some(Reduction {
+                    amount,
+                    available_at,
+                    paused_for: cfg.cashing_paused_time(now)?,
+                });
+                recipients().save(deps.storage, key, &recipient)?;
+
+                return Ok(Response::new()
+                    .add_attribute("method", "reduce_channel")
+                    .add_event(
+                        channel_event("channel_reduce_announced", key.0, key.1)
+                            .add_attribute("amount", amount.to_string())
+                            .add_attribute("available_at", available_at.to_string()),
+                    ));
+            }
+        }
+    }
+
+    recipient.max_amount = Uint128::new(recipient.max_amount)
+        .checked_sub(Uint128::new(amount))?
+        .u128();
+    recipient.reduction = None;
+    recipient.reductions = Uint64::new(recipient.reductions)
+        .checked_add(Uint64::new(1))?
+        .u64();
+    recipients().save(deps.storage, key, &recipient)?;
+
+    let sub_msgs = build_transfer_msg(&payment_chan.denom, info.sender.to_string(), amount)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "reduce_channel")
+        .add_event(
+            channel_event("channel_reduced", key.0, key.1)
+                .add_attribute("amount", amount.to_string())
+                .add_attribute("max_amount", recipient.max_amount.to_string()),
+        )
+        .add_submessages(sub_msgs))
+}
+
+/// Cash the final cheque of the channel from `sender_pubkey_hash` and settle it at once, the
+/// remainder going back to the operator. Only the payout address of the recipient can close
+/// without a challenge period.
+#[constraints(not_paused(deps.as_ref(), Pausable::Cashing))]
+pub fn settle_and_close(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    sender_pubkey_hash: String,
+    final_cheque: PaymentCheque,
+) -> Result<Response, ContractError> {
+    let mut payment_chan = load_payment_chan(deps.storage, &sender_pubkey_hash)?;
+    let recipient_pubkey_hash = final_cheque.recipient_key.clone();
+
+    let payout = load_payout(deps.storage, &recipient_pubkey_hash)?;
+    if payout.address != info.sender {
+        return Err(ContractError::NotPayoutAddress {
+            sender: info.sender.to_string(),
+        });
+    }
+
+    if final_cheque.sender_key != sender_pubkey_hash {
+        return Err(ContractError::ChecksVerifyFailed);
+    }
+    let (cash, events) = cash_cheques(
+        deps.branch(),
+        &env,
+        &recipient_pubkey_hash,
+        vec![final_cheque],
+    )?;
+
+    let key = (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str());
+    let mut recipient = load_recipient(deps.storage, key.0, key.1)?;
+    let refund = recipient.settle()?;
+    recipients().save(deps.storage, key, &recipient)?;
+    payment_chan.remove_recipient()?;
+    PAYMENT_CHANNELS.save(deps.storage, &sender_pubkey_hash, &payment_chan)?;
+
+    let denom = &payment_chan.denom;
+    let fee = CONFIG.load(deps.storage)?.fee;
+    let mut sub_msgs = vec![];
+    let mut fee_events = vec![];
+    for (_, cash) in cash {
+        let (msgs, event) =
+            build_payout_msgs(fee.as_ref(), denom, payout.address.to_string(), cash.u128())?;
+        sub_msgs.extend(msgs);
+        fee_events.extend(event);
+    }
+    if refund > 0 {
+        sub_msgs.extend(build_transfer_msg(denom, payment_chan.operator, refund)?);
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "settle_and_close")
+        .add_events(events)
+        .add_events(fee_events)
+        .add_event(
+            channel_event("channel_closed", key.0, key.1)
+                .add_attribute("refund", refund.to_string())
+                .add_attribute("cooperative", "true"),
+        )
+        .add_submessages(sub_msgs))
+}
+
+fn load_payout(
+    storage: &dyn Storage,
+    recipient_pubkey_hash: &str,
+) -> Result<Payout, ContractError> {
+    PAYOUTS
+        .may_load(storage, recipient_pubkey_hash.to_string())?
+        .ok_or(ContractError::PayoutNotRegistered)
+}
+
+/// Verify `cheques` and record them as cashed, returning the total amount to pay out per denom
+fn cash_cheques(
+    deps: DepsMut,
+    env: &Env,
+    recipient_pubkey_hash: &str,
+    cheques: Vec<PaymentCheque>,
+) -> Result<(Vec<(Denom, Uint128)>, Vec<Event>), ContractError> {
+    payment_check_interval_verify(deps.as_ref(), env, recipient_pubkey_hash, &cheques)?;
+
+    let mut total_cash: Vec<(Denom, Uint128)> = vec![];
+    let mut events = vec![];
     for cheque in cheques {
-        let mut payment_chan = PAYMENT_CHANNELS.load(deps.storage, cheque.sender_key.clone())?;
+        let mut recipient =
+            load_recipient(deps.storage, &cheque.sender_key, recipient_pubkey_hash)?;
 
-        let recipient = payment_chan
-            .recipients
-            .get_mut(recipient_pubkey_hash.as_str())
-            .unwrap();
+        let remain = Uint128::new(recipient.remain()?);
+        let cash = match (recipient.face_value, cheque.value) {
+            (Some(face_value), None) => {
+                let nonce_delta = cheque
+                    .nonce
+                    .checked_sub(recipient.nonce_withdrawl.unwrap_or(0))
+                    .ok_or(ContractError::NonceNotIncreasing)?;
+                Uint128::from(nonce_delta)
+                    .checked_mul(Uint128::new(face_value))
+                    .map_err(|_| ContractError::Overdraw)?
+            }
+            // any value cheques carry the cumulative amount paid through the channel
+            (None, Some(value)) => {
+                let withdrawn = recipient.withdrawn_amount.unwrap_or(0);
+                if value <= withdrawn {
+                    return Err(ContractError::InvalidChequeValue);
+                }
 
-        assert!(recipient.nonce_withdrawl.unwrap_or(0) < cheque.nonce);
+                recipient.withdrawn_amount = Some(value);
+                Uint128::new(value - withdrawn)
+            }
+            _ => return Err(ContractError::InvalidChequeValue),
+        };
 
-        total_cash += (cheque.nonce - recipient.nonce_withdrawl.unwrap_or(0)) as u128
-            * recipient.face_value.unwrap();
+        // a cheque can never draw on funds of other channels
+        if cash > remain {
+            return Err(ContractError::Overdraw);
+        }
+        let denom = load_payment_chan(deps.storage, &cheque.sender_key)?.denom;
+        match total_cash.iter_mut().find(|(d, _)| *d == denom) {
+            Some((_, total)) => *total = total.checked_add(cash)?,
+            None => total_cash.push((denom, cash)),
+        }
         recipient.nonce_withdrawl = Some(cheque.nonce);
-        PAYMENT_CHANNELS.save(deps.storage, cheque.sender_key, &payment_chan)?;
+        recipient.prune_revoked();
+        recipients().save(
+            deps.storage,
+            (cheque.sender_key.as_str(), recipient_pubkey_hash),
+            &recipient,
+        )?;
+
+        events.push(
+            channel_event("cheque_cashed", &cheque.sender_key, recipient_pubkey_hash)
+                .add_attribute("nonce", cheque.nonce.to_string())
+                .add_attribute("amount", cash)
+                .add_attribute("withdrawn", recipient.withdrawn()?.to_string()),
+        );
     }
 
-    let sub_msgs = build_transfer_msg(&cfg, info.sender.to_string(), total_cash)?;
+    Ok((total_cash, events))
+}
+
+/// Bind the payout address of `recipient_pubkey_hash`. The commitment proves the recipient signed
+/// the address, so relayers can submit it and later cheques without redirecting funds.
+pub fn register_payout(
+    deps: DepsMut,
+    env: Env,
+    recipient_pubkey_hash: String,
+    address: String,
+    nonce: u64,
+    commitment: Vec<u8>,
+) -> Result<Response, ContractError> {
+    let address = deps.api.addr_validate(&address)?;
+
+    let last_nonce = PAYOUTS
+        .may_load(deps.storage, recipient_pubkey_hash.clone())?
+        .map_or(0, |payout| payout.nonce);
+    if nonce <= last_nonce {
+        return Err(ContractError::NonceNotIncreasing);
+    }
+
+    verify_commitment(
+        deps.as_ref(),
+        &env,
+        &recipient_pubkey_hash,
+        CommitmentType::Payout {
+            recipient_key: &recipient_pubkey_hash,
+            address: address.as_str(),
+            nonce,
+        },
+        &commitment,
+    )?;
+
+    PAYOUTS.save(
+        deps.storage,
+        recipient_pubkey_hash,
+        &Payout {
+            address: address.clone(),
+            nonce,
+        },
+    )?;
 
     Ok(Response::new()
-        .add_attribute("method", "cashing")
-        .add_submessages(sub_msgs))
+        .add_attribute("method", "register_payout")
+        .add_attribute("address", address))
 }
 
-pub enum CommitmentType {
-    Cheque,
-    CloseChannel,
+pub enum CommitmentType<'a> {
+    Cheque {
+        sender_key: &'a str,
+        recipient_key: &'a str,
+        nonce: u64,
+        value: Option<u128>,
+    },
+    /// Sender request to close its channels, valid for the close following the first `closes`
+    /// ones only
+    CloseChannel { sender_key: &'a str, closes: u64 },
+    Payout {
+        recipient_key: &'a str,
+        address: &'a str,
+        nonce: u64,
+    },
+    /// Recipient consent to settle its channel at once, bound to the channel lifetime and the
+    /// amount withdrawn so far
+    CloseConsent {
+        sender_key: &'a str,
+        recipient_key: &'a str,
+        epoch: u64,
+        withdrawn: u128,
+    },
+    /// Consent to lower the balance of the channel from `max_amount` by `amount`, valid for the
+    /// reduction following the first `reductions` ones only
+    ReduceChannel {
+        sender_key: &'a str,
+        recipient_key: &'a str,
+        max_amount: u128,
+        amount: u128,
+        reductions: u64,
+    },
+}
+
+impl CommitmentType<'_> {
+    /// Preimage of the circuit `msg` public input. Strings are length prefixed and integers are
+    /// big endian so that clients can rebuild it byte for byte. It starts with the chain id and
+    /// the contract address, a commitment is only valid for the deployment it was signed for.
+    pub fn encode(&self, env: &Env) -> Vec<u8> {
+        fn push_str(res: &mut Vec<u8>, s: &str) {
+            res.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            res.extend_from_slice(s.as_bytes());
+        }
+
+        let mut res = vec![];
+        push_str(&mut res, &env.block.chain_id);
+        push_str(&mut res, env.contract.address.as_str());
+        match self {
+            CommitmentType::Cheque {
+                sender_key,
+                recipient_key,
+                nonce,
+                value,
+            } => {
+                res.push(0);
+                push_str(&mut res, sender_key);
+                push_str(&mut res, recipient_key);
+                res.extend_from_slice(&nonce.to_be_bytes());
+                if let Some(value) = value {
+                    res.push(1);
+                    res.extend_from_slice(&value.to_be_bytes());
+                } else {
+                    res.push(0);
+                }
+            }
+            CommitmentType::CloseChannel { sender_key, closes } => {
+                res.push(1);
+                push_str(&mut res, sender_key);
+                res.extend_from_slice(&closes.to_be_bytes());
+            }
+            CommitmentType::Payout {
+                recipient_key,
+                address,
+                nonce,
+            } => {
+                res.push(2);
+                push_str(&mut res, recipient_key);
+                push_str(&mut res, address);
+                res.extend_from_slice(&nonce.to_be_bytes());
+            }
+            CommitmentType::ReduceChannel {
+                sender_key,
+                recipient_key,
+                max_amount,
+                amount,
+                reductions,
+            } => {
+                res.push(3);
+                push_str(&mut res, sender_key);
+                push_str(&mut res, recipient_key);
+                res.extend_from_slice(&max_amount.to_be_bytes());
+                res.extend_from_slice(&amount.to_be_bytes());
+                res.extend_from_slice(&reductions.to_be_bytes());
+            }
+            CommitmentType::CloseConsent {
+                sender_key,
+                recipient_key,
+                epoch,
+                withdrawn,
+            } => {
+                res.push(4);
+                push_str(&mut res, sender_key);
+                push_str(&mut res, recipient_key);
+                res.extend_from_slice(&epoch.to_be_bytes());
+                res.extend_from_slice(&withdrawn.to_be_bytes());
+            }
+        }
+
+        res
+    }
 }
 
+/// Verify the Groth16 proof that the owner of `signer_pubkey_hash` signed `commitment_type`.
+/// Proofs made for a rotated out verifying key are accepted until its grace window ends.
 fn verify_commitment(
-    _sender_pubkey_hash: &str,
-    _commitment_type: CommitmentType,
-    _commitment: Vec<u8>,
+    deps: Deps,
+    env: &Env,
+    signer_pubkey_hash: &str,
+    commitment_type: CommitmentType,
+    commitment: &[u8],
 ) -> Result<(), ContractError> {
-    // TODO:
-    Ok(())
+    let inputs = zkp::public_inputs(signer_pubkey_hash, &commitment_type.encode(env))
+        .ok_or(ContractError::ChecksVerifyFailed)?;
+
+    let vk = VERIFYING_KEY.load(deps.storage)?;
+    if zkp::verify_proof(vk.key.as_slice(), commitment, &inputs) {
+        return Ok(());
+    }
+
+    let now = env.block.time.seconds();
+    for item in RETIRED_VERIFYING_KEYS.range(deps.storage, None, None, Order::Descending) {
+        let (_, vk) = item?;
+        if vk.is_active(now) && zkp::verify_proof(vk.key.as_slice(), commitment, &inputs) {
+            return Ok(());
+        }
+    }
+
+    Err(ContractError::ChecksVerifyFailed)
 }
 fn payment_check_interval_verify(
-    _deps: Deps,
-    checks: &Vec<PaymentCheque>,
+    deps: Deps,
+    env: &Env,
+    recipient_pubkey_hash: &str,
+    checks: &[PaymentCheque],
 ) -> Result<(), ContractError> {
-    // TODO:
-    for _start in checks {
-        // verify commitment and noce
+    let mut senders = HashSet::new();
+    for check in checks {
+        // one cheque per sender, the latest one carries the accumulated nonce
+        if !senders.insert(check.sender_key.as_str()) {
+            return Err(ContractError::DuplicateCheque {
+                sender_key: check.sender_key.clone(),
+            });
+        }
+
+        if check.recipient_key != recipient_pubkey_hash {
+            return Err(ContractError::ChecksVerifyFailed);
+        }
+
+        let recipient = load_recipient(deps.storage, &check.sender_key, recipient_pubkey_hash)?;
+        // cheques can be cashed until the channel is settled, including during its challenge period
+        if recipient.state == ChannelState::Settled {
+            return Err(ContractError::ChannelSettled);
+        }
+        if check.nonce <= recipient.nonce_withdrawl.unwrap_or(0) {
+            return Err(ContractError::NonceNotIncreasing);
+        }
+
+        // delegated signers sign on behalf of the sender for this channel only
+        let signer_key = check.signer_key.as_ref().unwrap_or(&check.sender_key);
+        if *signer_key != check.sender_key && !recipient.is_approved(signer_key, check.nonce) {
+            return Err(ContractError::UnapprovedSigner {
+                signer_key: signer_key.clone(),
+            });
+        }
+
+        let commitment_type = || CommitmentType::Cheque {
+            sender_key: &check.sender_key,
+            recipient_key: &check.recipient_key,
+            nonce: check.nonce,
+            value: check.value,
+        };
+        verify_commitment(
+            deps,
+            env,
+            signer_key,
+            commitment_type(),
+            &check.sender_commitment,
+        )?;
+        verify_commitment(
+            deps,
+            env,
+            &check.recipient_key,
+            commitment_type(),
+            &check.recipient_commitment,
+        )?;
     }
+
     Ok(())
 }
 
@@ -264,26 +1250,272 @@ pub fn update_config(
     _env: Env,
     info: MessageInfo,
     auto_release_time: Option<u64>,
-    owner: Option<String>,
     max_recipient: Option<u32>,
+    fee: Option<Fee>,
 ) -> Result<Response, ContractError> {
     let mut config = CONFIG.load(deps.storage)?;
+    let mut event = Event::new("config_updated");
 
     if let Some(auto_release_time) = auto_release_time {
         config.auto_release_time = auto_release_time;
-    }
-
-    if let Some(owner) = owner {
-        config.owner = deps.api.addr_canonicalize(&owner)?;
+        event = event.add_attribute("auto_release_time", auto_release_time.to_string());
     }
 
     if let Some(max_recipient) = max_recipient {
         config.max_recipient = max_recipient;
+        event = event.add_attribute("max_recipient", max_recipient.to_string());
+    }
+
+    if let Some(fee) = fee {
+        if fee.bps > MAX_FEE_BPS {
+            return Err(ContractError::InvalidFee {
+                max_bps: MAX_FEE_BPS,
+            });
+        }
+        deps.api.addr_validate(fee.collector.as_str())?;
+        let min_amounts = fee
+            .min_amounts
+            .iter()
+            .map(|(denom, min_amount)| format!("{}={min_amount}", denom.key()))
+            .collect::<Vec<_>>();
+        event = event
+            .add_attribute("fee_bps", fee.bps.to_string())
+            .add_attribute("fee_min_amounts", min_amounts.join(","))
+            .add_attribute("fee_collector", fee.collector.to_string());
+        config.fee = Some(fee);
     }
 
     CONFIG.save(deps.storage, &config)?;
 
-    Ok(Response::new().add_attribute("method", "update_config"))
+    Ok(Response::new()
+        .add_attribute("method", "update_config")
+        .add_event(event))
+}
+
+/// Propose `new_owner`. The current owner stays in charge until the proposed one accepts, so a
+/// mistyped address can never take over.
+// admin only
+#[constraints(owner_only(deps.as_ref(), &info, None))]
+pub fn propose_owner(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_owner: String,
+    expires_in: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    let expires_at = expires_in
+        .map(|expires_in| add_seconds(env.block.time.seconds(), expires_in))
+        .transpose()?;
+    config.pending_owner = Some(PendingOwner {
+        owner: deps.api.addr_canonicalize(new_owner.as_str())?,
+        expires_at,
+    });
+
+    CONFIG.save(deps.storage, &config)?;
+
+    let mut event = Event::new("owner_transfer_proposed")
+        .add_attribute("owner", info.sender)
+        .add_attribute("new_owner", new_owner);
+    if let Some(expires_at) = expires_at {
+        event = event.add_attribute("expires_at", expires_at.to_string());
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "propose_owner")
+        .add_event(event))
+}
+
+pub fn accept_owner(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    let sender = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let pending_owner = config
+        .pending_owner
+        .take()
+        .filter(|pending_owner| pending_owner.owner == sender)
+        .ok_or_else(|| ContractError::NotPendingOwner {
+            sender: info.sender.to_string(),
+        })?;
+    if let Some(expires_at) = pending_owner.expires_at {
+        if env.block.time.seconds() > expires_at {
+            return Err(ContractError::OwnerProposalExpired { expires_at });
+        }
+    }
+
+    let old_owner = std::mem::replace(&mut config.owner, pending_owner.owner);
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "accept_owner")
+        .add_event(
+            Event::new("owner_transferred")
+                .add_attribute("old_owner", deps.api.addr_humanize(&old_owner)?)
+                .add_attribute("owner", info.sender),
+        ))
+}
+
+// admin only
+#[constraints(owner_only(deps.as_ref(), &info, None))]
+pub fn cancel_owner_proposal(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    let pending_owner = config
+        .pending_owner
+        .take()
+        .ok_or(ContractError::NoPendingOwner)?;
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "cancel_owner_proposal")
+        .add_event(
+            Event::new("owner_transfer_cancelled")
+                .add_attribute("new_owner", deps.api.addr_humanize(&pending_owner.owner)?),
+        ))
+}
+
+/// Pause, or resume when `paused` is false, the operations set in `flags`. Other flags are left
+/// as they are.
+// admin only
+#[constraints(owner_only(deps.as_ref(), &info, None))]
+pub fn set_paused(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    flags: PauseFlags,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if flags.deposits {
+        config.paused.deposits = paused;
+    }
+    if flags.cashing && config.paused.cashing != paused {
+        let now = env.block.time.seconds();
+        config.cashing_paused_for = config.cashing_paused_time(now)?;
+        config.cashing_paused_at = paused.then_some(now);
+        config.paused.cashing = paused;
+    }
+    if flags.closing {
+        config.paused.closing = paused;
+    }
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", if paused { "pause" } else { "unpause" })
+        .add_event(
+            Event::new("paused")
+                .add_attribute("deposits", config.paused.deposits.to_string())
+                .add_attribute("cashing", config.paused.cashing.to_string())
+                .add_attribute("closing", config.paused.closing.to_string()),
+        ))
+}
+
+// admin only
+#[constraints(owner_only(deps.as_ref(), &info, None))]
+pub fn update_verifying_key(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    verifying_key: Binary,
+    grace_period: Option<u64>,
+) -> Result<Response, ContractError> {
+    if !zkp::is_valid_verifying_key(verifying_key.as_slice()) {
+        return Err(ContractError::InvalidVerifyingKey);
+    }
+
+    let now = env.block.time.seconds();
+    let expired = RETIRED_VERIFYING_KEYS
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter(|item| item.as_ref().map_or(true, |(_, vk)| !vk.is_active(now)))
+        .map(|item| item.map(|(id, _)| id))
+        .collect::<StdResult<Vec<_>>>()?;
+    for id in expired {
+        RETIRED_VERIFYING_KEYS.remove(deps.storage, id);
+    }
+
+    // keep accepting proofs of the old key during the grace window
+    let mut retired = VERIFYING_KEY.load(deps.storage)?;
+    if let Some(grace_period) = grace_period.filter(|grace_period| *grace_period > 0) {
+        retired.expires_at = Some(add_seconds(now, grace_period)?);
+        RETIRED_VERIFYING_KEYS.save(deps.storage, retired.id, &retired)?;
+    }
+
+    let id = Uint64::new(retired.id).checked_add(Uint64::new(1))?.u64();
+    VERIFYING_KEY.save(
+        deps.storage,
+        &VerifyingKeyInfo {
+            id,
+            key: verifying_key,
+            expires_at: None,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "update_verifying_key")
+        .add_attribute("key_id", id.to_string()))
+}
+
+// admin only
+#[constraints(owner_only(deps.as_ref(), &info, None))]
+pub fn update_denoms(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<Denom>,
+    remove: Vec<Denom>,
+) -> Result<Response, ContractError> {
+    // channels already funded in a removed denom keep paying out in it
+    for denom in remove.iter() {
+        ACCEPTED_DENOMS.remove(deps.storage, denom.key());
+    }
+
+    for denom in add.iter() {
+        if let Denom::Cw20(addr) = denom {
+            deps.api.addr_validate(addr.as_str())?;
+        }
+        ACCEPTED_DENOMS.save(deps.storage, denom.key(), denom)?;
+    }
+
+    let keys = |denoms: &[Denom]| denoms.iter().map(Denom::key).collect::<Vec<_>>().join(",");
+    Ok(Response::new()
+        .add_attribute("method", "update_denoms")
+        .add_event(
+            Event::new("denoms_updated")
+                .add_attribute("added", keys(&add))
+                .add_attribute("removed", keys(&remove)),
+        ))
+}
+
+/// Operations the owner can pause
+#[derive(Clone, Copy)]
+enum Pausable {
+    Deposits,
+    Cashing,
+}
+
+fn not_paused(deps: Deps, operation: Pausable) -> Result<bool, ContractError> {
+    let paused = CONFIG.load(deps.storage)?.paused;
+    let (is_paused, name) = match operation {
+        Pausable::Deposits => (paused.deposits, "Deposits"),
+        Pausable::Cashing => (paused.cashing, "Cashing"),
+    };
+    if is_paused {
+        return Err(ContractError::Paused {
+            operation: name.to_string(),
+        });
+    }
+
+    Ok(true)
 }
 
 fn owner_only(
@@ -304,42 +1536,110 @@ fn owner_only(
     Ok(true)
 }
 
-const DEFAULT_SIZE: u32 = 10;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
+/// Recipient channels of `sender_pubkey_hash` ordered by recipient key
 pub fn payment_chan(
     deps: Deps,
     _env: Env,
     sender_pubkey_hash: String,
     recipient_pubkey_hash: Option<String>,
-    page: Option<u32>,
-    size: Option<u32>,
-) -> StdResult<Option<Vec<Recipient>>> {
-    let chan = PAYMENT_CHANNELS.load(deps.storage, sender_pubkey_hash)?;
-    let mut res = vec![];
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<RecipientInfo>> {
+    PAYMENT_CHANNELS.load(deps.storage, &sender_pubkey_hash)?;
     if let Some(recipient_pubkey_hash) = recipient_pubkey_hash {
-        let recipient_chan = chan.recipients.get(&recipient_pubkey_hash);
-        if let Some(rc) = recipient_chan {
-            res.push(rc.clone());
-        }
-    } else {
-        let size = size.unwrap_or(DEFAULT_SIZE);
-        let start = page.unwrap_or(0) * size;
+        let key = (sender_pubkey_hash.as_str(), recipient_pubkey_hash.as_str());
+        let res = recipients()
+            .may_load(deps.storage, key)?
+            .map(|recipient| RecipientInfo {
+                key: recipient_pubkey_hash,
+                recipient,
+            });
+        return Ok(res.into_iter().collect());
+    }
 
-        for (i, v) in chan.recipients.iter().enumerate() {
-            if i >= start as usize {
-                res.push(v.1.clone());
-            }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
 
-            if i > (start + size) as usize {
-                break;
-            }
-        }
-    }
+    recipients()
+        .prefix(sender_pubkey_hash.as_str())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(key, recipient)| RecipientInfo { key, recipient }))
+        .collect()
+}
 
-    Ok(Some(res))
+/// Recipient channels paying `recipient_pubkey_hash` ordered by sender key
+pub fn channels_by_recipient(
+    deps: Deps,
+    recipient_pubkey_hash: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<ChannelInfo>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .as_deref()
+        .map(|sender| Bound::exclusive((sender, recipient_pubkey_hash.as_str())));
+
+    recipients()
+        .idx
+        .recipient
+        .prefix(recipient_pubkey_hash.clone())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|((sender_key, _), recipient)| ChannelInfo {
+                sender_key,
+                recipient,
+            })
+        })
+        .collect()
+}
+
+pub fn recipient_count(deps: Deps, sender_pubkey_hash: String) -> StdResult<u32> {
+    let count = PAYMENT_CHANNELS
+        .may_load(deps.storage, &sender_pubkey_hash)?
+        .map_or(0, |chan| chan.recipient_count);
+    Ok(count)
+}
+
+pub fn payout(deps: Deps, recipient_pubkey_hash: String) -> StdResult<Option<Payout>> {
+    PAYOUTS.may_load(deps.storage, recipient_pubkey_hash)
+}
+
+pub fn accepted_denoms(deps: Deps) -> StdResult<Vec<Denom>> {
+    ACCEPTED_DENOMS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, denom)| denom))
+        .collect()
 }
 
 pub fn config(deps: Deps) -> StdResult<Config> {
     let config = CONFIG.load(deps.storage)?;
     Ok(config)
 }
+
+pub fn fee_estimate(deps: Deps, denom: Denom, amount: u128) -> StdResult<FeeEstimateResponse> {
+    let fee = CONFIG
+        .load(deps.storage)?
+        .fee
+        .map_or(0, |fee| fee.compute(&denom, amount));
+    Ok(FeeEstimateResponse {
+        fee,
+        payout: amount - fee,
+    })
+}
+
+pub fn verifying_key(deps: Deps, env: Env) -> StdResult<VerifyingKeyResponse> {
+    let now = env.block.time.seconds();
+    let current = VERIFYING_KEY.load(deps.storage)?;
+    let retired = RETIRED_VERIFYING_KEYS
+        .range(deps.storage, None, None, Order::Descending)
+        .filter(|item| item.as_ref().map_or(true, |(_, vk)| vk.is_active(now)))
+        .map(|item| item.map(|(_, vk)| vk))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(VerifyingKeyResponse { current, retired })
+}
@@ -1,7 +1,8 @@
 pub mod contract;
 pub mod error;
 pub mod handler;
-pub mod mock;
+#[cfg(test)]
+mod mock;
 pub mod msg;
 mod state;
 #[cfg(test)]
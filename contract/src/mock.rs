@@ -1 +1,86 @@
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Groth16, ProvingKey};
+use ark_relations::{
+    lc,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+use ark_serialize::CanonicalSerialize;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+use cosmwasm_std::{testing::mock_env, Binary, Env};
 
+use crate::{handler::CommitmentType, zkp};
+
+/// Stand-in for `payment.circom` exposing the same public inputs `[msg, sender_pubkey_hash]`,
+/// so that proofs can be generated for tests without a trusted setup of the real circuit.
+#[derive(Clone, Default)]
+struct MockCircuit {
+    inputs: Option<[Fr; 2]>,
+}
+
+impl ConstraintSynthesizer<Fr> for MockCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let inputs = || self.inputs.ok_or(SynthesisError::AssignmentMissing);
+        let msg = cs.new_input_variable(|| Ok(inputs()?[0]))?;
+        let pubkey_hash = cs.new_input_variable(|| Ok(inputs()?[1]))?;
+        let product = cs.new_witness_variable(|| Ok(inputs()?[0] * inputs()?[1]))?;
+
+        cs.enforce_constraint(lc!() + msg, lc!() + pubkey_hash, lc!() + product)?;
+
+        Ok(())
+    }
+}
+
+pub struct MockProver {
+    pk: ProvingKey<Bn254>,
+}
+
+impl MockProver {
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// Provers built from different seeds have distinct verifying keys
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(
+            MockCircuit::default(),
+            &mut rng,
+        )
+        .unwrap();
+
+        Self { pk }
+    }
+
+    pub fn verifying_key(&self) -> Binary {
+        let mut vk = vec![];
+        self.pk.vk.serialize_compressed(&mut vk).unwrap();
+        Binary::from(vk)
+    }
+
+    /// Prove `commitment` signed by the owner of `signer_key` for the deployment of `mock_env`
+    pub fn prove(&self, signer_key: &str, commitment: CommitmentType) -> Vec<u8> {
+        self.prove_for(&mock_env(), signer_key, commitment)
+    }
+
+    /// Prove `commitment` signed for the chain and contract of `env`
+    pub fn prove_for(&self, env: &Env, signer_key: &str, commitment: CommitmentType) -> Vec<u8> {
+        let circuit = MockCircuit {
+            inputs: zkp::public_inputs(signer_key, &commitment.encode(env)),
+        };
+
+        let mut rng = ark_std::test_rng();
+        let proof =
+            Groth16::<Bn254>::create_random_proof_with_reduction(circuit, &self.pk, &mut rng)
+                .unwrap();
+
+        let mut res = vec![];
+        proof.serialize_compressed(&mut res).unwrap();
+        res
+    }
+}
+
+impl Default for MockProver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
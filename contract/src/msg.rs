@@ -1,16 +1,20 @@
 use crate::state::Denom;
-use crate::state::{Config, Recipient};
+use crate::state::{Config, Fee, PauseFlags, Payout, Recipient, VerifyingKeyInfo};
 use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::Binary;
 use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
+    /// First accepted denom, more can be added with `UpdateDenoms`
     pub denom: Denom,
     pub admin: Option<String>,
     pub auto_release_time: u64,
     pub max_recipient: u32,
+    /// Compressed Groth16 verifying key of the payment circuit
+    pub verifying_key: Binary,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -26,20 +30,95 @@ pub enum ExecuteMsg {
         commitment: Vec<u8>,
         channels: Vec<(String, Vec<u8>)>,
     },
+    /// Add the attached funds to the balance of an existing recipient channel
+    TopUp {
+        chan_key: String,
+        recipient_key: String,
+    },
+    /// Refund `amount` of the channel balance to the operator. Without the recipient `commitment`
+    /// the first call announces the reduction and a second one after `auto_release_time` applies it
+    ReduceChannel {
+        chan_key: String,
+        recipient_key: String,
+        amount: u128,
+        commitment: Option<Vec<u8>>,
+    },
     AddSigner {
         chan_key: String,
         recipient_key: String,
         signers: Vec<String>,
     },
+    /// Propose a new operator for the payment channel, effective once it sends `AcceptOperator`
+    TransferOperator {
+        chan_key: String,
+        new_operator: String,
+    },
+    AcceptOperator {
+        chan_key: String,
+    },
+    /// Revoke an approved signer. Its cheques up to `nonce`, the withdrawn nonce by default, stay valid
+    RemoveSigner {
+        chan_key: String,
+        recipient_key: String,
+        signer: String,
+        nonce: Option<u64>,
+    },
+    /// Replace the approved signers, revoking the dropped ones as `RemoveSigner` does
+    ReplaceSigners {
+        chan_key: String,
+        recipient_key: String,
+        signers: Vec<String>,
+        nonce: Option<u64>,
+    },
+    /// Cash cheques to the payout address registered for `recipient_key`
     Cashing {
         recipient_key: String,
         cheques: Vec<PaymentCheque>,
     },
-    /// Change the admin
+    /// Cash the final cheque of a channel and settle it, refunding the remainder to the operator.
+    /// Must be sent from the payout address of the cheque recipient.
+    SettleAndClose {
+        sender_key: String,
+        final_cheque: PaymentCheque,
+    },
+    /// Bind the address cashed funds of `recipient_key` are paid to
+    RegisterPayout {
+        recipient_key: String,
+        address: String,
+        nonce: u64,
+        commitment: Vec<u8>,
+    },
     UpdateConfig {
-        owner: Option<String>,
         auto_release_time: Option<u64>,
         max_recipient: Option<u32>,
+        /// Replace the protocol fee, a zero rate without minimums disables it
+        fee: Option<Fee>,
+    },
+    /// Propose a new owner, effective once it sends `AcceptOwner` within `expires_in` seconds
+    ProposeOwner {
+        new_owner: String,
+        expires_in: Option<u64>,
+    },
+    AcceptOwner {},
+    /// Withdraw the pending ownership proposal
+    CancelOwnerProposal {},
+    /// Halt the operations whose flag is set
+    Pause {
+        flags: PauseFlags,
+    },
+    /// Resume the operations whose flag is set
+    Unpause {
+        flags: PauseFlags,
+    },
+    /// Change the denoms new channels can be funded with
+    UpdateDenoms {
+        add: Vec<Denom>,
+        remove: Vec<Denom>,
+    },
+    /// Rotate the circuit verifying key. Proofs of the previous key stay valid for `grace_period` seconds
+    UpdateVerifyingKey {
+        verifying_key: Binary,
+        grace_period: Option<u64>,
     },
     /// This accepts a properly-encoded ReceiveMsg from a cw20 contract
     Receive(Cw20ReceiveMsg),
@@ -59,6 +138,9 @@ pub struct Channel {
 pub struct PaymentCheque {
     pub sender_key: String,
     pub sender_commitment: Vec<u8>,
+    /// Key of the approved signer of `sender_commitment`, the sender key itself when `None`
+    #[serde(default)]
+    pub signer_key: Option<String>,
     pub recipient_key: String,
     pub recipient_commitment: Vec<u8>,
     pub value: Option<u128>,
@@ -68,16 +150,71 @@ pub struct PaymentCheque {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, QueryResponses)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    #[returns(Option<Vec<Recipient>>)]
+    /// Recipient channels of a sender ordered by recipient key, paginated after `start_after`
+    #[returns(Vec<RecipientInfo>)]
     PaymentChan {
         sender_pubkey_hash: String,
         recipient_pubkey_hash: Option<String>,
-        page: Option<u32>,
-        size: Option<u32>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Recipient channels paying a recipient ordered by sender key, paginated after `start_after`
+    #[returns(Vec<ChannelInfo>)]
+    ChannelsByRecipient {
+        recipient_key: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
     },
+    #[returns(Option<Payout>)]
+    Payout { recipient_pubkey_hash: String },
+    /// Number of recipients in the payment channel of the given sender
+    #[returns(u32)]
+    RecipientCount { sender_pubkey_hash: String },
+    /// Denoms channels can be funded with
+    #[returns(Vec<Denom>)]
+    AcceptedDenoms {},
     #[returns(Config)]
     Config {},
+    #[returns(VerifyingKeyResponse)]
+    VerifyingKey {},
+    /// Protocol fee taken when cashing `amount` of `denom`
+    #[returns(FeeEstimateResponse)]
+    FeeEstimate { denom: Denom, amount: u128 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RecipientInfo {
+    pub key: String,
+    pub recipient: Recipient,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ChannelInfo {
+    pub sender_key: String,
+    pub recipient: Recipient,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct VerifyingKeyResponse {
+    pub current: VerifyingKeyInfo,
+    /// Rotated out keys still within their grace window
+    pub retired: Vec<VerifyingKeyInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FeeEstimateResponse {
+    pub fee: u128,
+    /// Amount left for the recipient
+    pub payout: u128,
 }
 
 #[cw_serde]
-pub struct MigrateMsg {}
+pub struct MigrateMsg {
+    /// Compressed Groth16 verifying key, required when migrating from a version which did not
+    /// store one. Later rotations go through `UpdateVerifyingKey`.
+    pub verifying_key: Option<Binary>,
+}
@@ -1,12 +1,55 @@
 use std::collections::HashMap;
 
-use cosmwasm_std::{Addr, CanonicalAddr};
+use cosmwasm_std::{
+    Addr, Binary, CanonicalAddr, ConversionOverflowError, Order, OverflowError, OverflowOperation,
+    StdResult, Storage, Uint128, Uint64,
+};
 use cosmwasm_tools::config_item;
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, KeyDeserialize, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-pub const PAYMENT_CHANNELS: Map<String, PaymentChannel> = Map::new("payment_channel");
+use crate::zkp;
+
+/// Payment channel header of each sender key
+pub const PAYMENT_CHANNELS: Map<&str, PaymentChannel> = Map::new("payment_chan");
+/// Layout before per recipient storage, only read by `migrate_payment_channels`
+pub const LEGACY_PAYMENT_CHANNELS: Map<String, LegacyPaymentChannel> = Map::new("payment_channel");
+/// Denoms channels can be funded with, keyed by `Denom::key`
+pub const ACCEPTED_DENOMS: Map<String, Denom> = Map::new("accepted_denoms");
+/// Payout address of each recipient key
+pub const PAYOUTS: Map<String, Payout> = Map::new("payouts");
+pub const VERIFYING_KEY: Item<VerifyingKeyInfo> = Item::new("verifying_key");
+/// Rotated out verifying keys which are still accepted until their grace window ends
+pub const RETIRED_VERIFYING_KEYS: Map<u64, VerifyingKeyInfo> = Map::new("retired_verifying_keys");
+
+pub struct RecipientIndexes<'a> {
+    /// Reverse index from a recipient key to the senders paying it
+    pub recipient: MultiIndex<'a, String, Recipient, (&'a str, &'a str)>,
+}
+
+impl<'a> IndexList<Recipient> for RecipientIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Recipient>> + '_> {
+        let v: Vec<&dyn Index<Recipient>> = vec![&self.recipient];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Recipient channels keyed by (sender key, recipient key)
+pub fn recipients<'a>() -> IndexedMap<'a, (&'a str, &'a str), Recipient, RecipientIndexes<'a>> {
+    let indexes = RecipientIndexes {
+        recipient: MultiIndex::new(
+            |pk, _| {
+                <(&str, &str)>::from_slice(pk)
+                    .map(|(_, recipient)| recipient)
+                    .unwrap_or_default()
+            },
+            "recipients",
+            "recipients__recipient",
+        ),
+    };
+    IndexedMap::new("recipients", indexes)
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -15,44 +58,447 @@ pub enum Denom {
     Cw20(Addr),
 }
 
+impl Denom {
+    /// Native denom or cw20 contract address
+    pub fn name(&self) -> String {
+        match self {
+            Denom::Native(denom) => denom.clone(),
+            Denom::Cw20(addr) => addr.to_string(),
+        }
+    }
+
+    /// Name prefixed with the variant, so that a native denom and a cw20 contract sharing a name
+    /// never share a key
+    pub fn key(&self) -> String {
+        match self {
+            Denom::Native(denom) => format!("native:{denom}"),
+            Denom::Cw20(addr) => format!("cw20:{addr}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Payout {
+    pub address: Addr,
+    /// Nonce of the registration commitment, older registrations can not be replayed
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct VerifyingKeyInfo {
+    pub id: u64,
+    /// Compressed Groth16 verifying key of the payment circuit
+    pub key: Binary,
+    /// Block time in seconds after which a retired key is no longer accepted
+    pub expires_at: Option<u64>,
+}
+
+impl VerifyingKeyInfo {
+    #[inline]
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires_at.map_or(true, |expires_at| now < expires_at)
+    }
+}
+
 #[config_item]
 pub struct Config {
+    /// Denom of the channels opened before each channel carried its own
     pub denom: Denom,
     pub auto_release_time: u64,
     pub owner: CanonicalAddr,
     pub max_recipient: u32,
+    /// Protocol fee taken from cashed amounts, none when unset
+    pub fee: Option<Fee>,
+    /// Proposed owner, effective once it accepts
+    pub pending_owner: Option<PendingOwner>,
+    #[serde(default)]
+    pub paused: PauseFlags,
+    /// Block time the ongoing cashing pause started at
+    #[serde(default)]
+    pub cashing_paused_at: Option<u64>,
+    /// Seconds cashing was paused for over the pauses that ended. Challenge and notice periods
+    /// only run while recipients can cash.
+    #[serde(default)]
+    pub cashing_paused_for: u64,
+}
+
+impl Config {
+    /// Seconds cashing was paused for up to `now`, including the ongoing pause
+    pub fn cashing_paused_time(&self, now: u64) -> StdResult<u64> {
+        let ongoing = match self.cashing_paused_at {
+            Some(paused_at) => Uint64::new(now).checked_sub(Uint64::new(paused_at))?,
+            None => Uint64::zero(),
+        };
+        Ok(Uint64::new(self.cashing_paused_for)
+            .checked_add(ongoing)?
+            .u64())
+    }
+}
+
+/// Operations halted by the owner, e.g. while a flaw in proof verification is being fixed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PauseFlags {
+    /// Opening and topping up channels
+    #[serde(default)]
+    pub deposits: bool,
+    /// Cashing cheques, including `SettleAndClose`
+    #[serde(default)]
+    pub cashing: bool,
+    /// Reductions and closes relying on the recipient consent. Senders can still close through
+    /// the challenge period.
+    #[serde(default)]
+    pub closing: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingOwner {
+    pub owner: CanonicalAddr,
+    /// Block time in seconds after which the proposal can no longer be accepted
+    pub expires_at: Option<u64>,
+}
+
+/// Maximum fee rate in basis points
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Fee {
+    /// Fee rate in basis points of the cashed amount
+    pub bps: u16,
+    /// Flat minimum of each denom in its own base units, charged per denom on each cashing.
+    /// Denoms not listed have none.
+    #[serde(default)]
+    pub min_amounts: Vec<(Denom, u128)>,
+    /// Address receiving the fees
+    pub collector: Addr,
+}
+
+impl Fee {
+    pub fn min_amount(&self, denom: &Denom) -> u128 {
+        self.min_amounts
+            .iter()
+            .find(|(d, _)| d == denom)
+            .map_or(0, |(_, min_amount)| *min_amount)
+    }
+
+    /// Fee charged on cashing `amount` of `denom`, never more than `amount` itself
+    pub fn compute(&self, denom: &Denom, amount: u128) -> u128 {
+        let fee = Uint128::new(amount)
+            .multiply_ratio(self.bps, MAX_FEE_BPS)
+            .u128();
+        fee.max(self.min_amount(denom)).min(amount)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct PaymentChannel {
     pub operator: String,
-    pub recipients: HashMap<String, Recipient>,
+    pub recipient_count: u32,
+    /// Denom every recipient channel of the sender is funded and paid out in
+    pub denom: Denom,
+    /// Operator proposed by `TransferOperator`, in charge once it accepts
+    #[serde(default)]
+    pub pending_operator: Option<String>,
+    /// Number of close requests made, a sender close commitment is only valid for the next one
+    #[serde(default)]
+    pub closes: u64,
+}
+
+impl PaymentChannel {
+    pub fn add_recipient(&mut self) -> StdResult<()> {
+        self.recipient_count = self
+            .recipient_count
+            .checked_add(1)
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Add, self.recipient_count, 1))?;
+        Ok(())
+    }
+
+    pub fn remove_recipient(&mut self) -> StdResult<()> {
+        self.recipient_count = self
+            .recipient_count
+            .checked_sub(1)
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Sub, self.recipient_count, 1))?;
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub struct Recipient {
+pub struct LegacyPaymentChannel {
+    pub operator: String,
+    pub recipients: HashMap<String, LegacyRecipient>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LegacyRecipient {
     pub max_amount: u128,
     pub nonce_withdrawl: Option<u64>,
     pub face_value: Option<u128>,
+    pub withdrawn_amount: Option<u128>,
     pub auto_release: Option<u64>,
     pub approve_signers: Vec<String>,
 }
 
+impl From<LegacyRecipient> for Recipient {
+    fn from(legacy: LegacyRecipient) -> Self {
+        Self {
+            max_amount: legacy.max_amount,
+            nonce_withdrawl: legacy.nonce_withdrawl,
+            face_value: legacy.face_value,
+            withdrawn_amount: legacy.withdrawn_amount,
+            state: legacy.auto_release.map_or(ChannelState::Open, |deadline| {
+                ChannelState::Closing {
+                    deadline,
+                    paused_for: 0,
+                }
+            }),
+            reduction: None,
+            approve_signers: legacy.approve_signers,
+            revoked_signers: vec![],
+            blocked_signers: vec![],
+            epoch: 0,
+            reductions: 0,
+        }
+    }
+}
+
+/// Lifecycle of a recipient channel
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelState {
+    #[default]
+    Open,
+    /// Close requested without the recipient consent. The recipient can still cash its latest
+    /// cheque until `deadline`, after which the remainder can be refunded. The deadline is
+    /// pushed back by the time cashing is paused once the period started, `paused_for` being
+    /// `Config::cashing_paused_time` at its start.
+    Closing {
+        deadline: u64,
+        #[serde(default)]
+        paused_for: u64,
+    },
+    /// Remainder refunded. Kept so that cheques of earlier nonces can not be replayed if the
+    /// channel is funded again.
+    Settled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Recipient {
+    pub max_amount: u128,
+    pub nonce_withdrawl: Option<u64>,
+    /// Value of every cheque. `None` for any value channels whose cheques carry the cumulative amount
+    pub face_value: Option<u128>,
+    /// Cumulative amount cashed from an any value channel
+    pub withdrawn_amount: Option<u128>,
+    #[serde(default)]
+    pub state: ChannelState,
+    /// Reduction announced by the operator without the recipient consent
+    #[serde(default)]
+    pub reduction: Option<Reduction>,
+    pub approve_signers: Vec<String>,
+    /// Removed signers whose cheques stay valid up to the recorded nonce
+    #[serde(default)]
+    pub revoked_signers: Vec<RevokedSigner>,
+    /// Removed signers without any cheque left to cash, kept so that they stay revoked
+    #[serde(default)]
+    pub blocked_signers: Vec<String>,
+    /// Number of times the channel was settled. Close consents only apply to the lifetime they
+    /// were signed in.
+    #[serde(default)]
+    pub epoch: u64,
+    /// Number of reductions applied, a reduce consent is only valid for the next one
+    #[serde(default)]
+    pub reductions: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RevokedSigner {
+    pub key: String,
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Reduction {
+    pub amount: u128,
+    /// Block time in seconds from which the reduction can be applied, pushed back by the time
+    /// cashing is paused as for `ChannelState::Closing`
+    pub available_at: u64,
+    #[serde(default)]
+    pub paused_for: u64,
+}
+
 impl Recipient {
     #[inline]
-    pub fn new(signers: Vec<String>, max_amount: u128, face_value: u128) -> Self {
-        Self {
+    pub fn new(signers: Vec<String>, max_amount: u128, face_value: Option<u128>) -> Self {
+        let mut recipient = Self {
             max_amount,
             nonce_withdrawl: None,
-            face_value: Some(face_value),
-            auto_release: None,
-            approve_signers: signers,
+            face_value,
+            withdrawn_amount: None,
+            state: ChannelState::Open,
+            reduction: None,
+            approve_signers: vec![],
+            revoked_signers: vec![],
+            blocked_signers: vec![],
+            epoch: 0,
+            reductions: 0,
+        };
+        recipient.approve(signers);
+        recipient
+    }
+
+    /// Add `signers` skipping the ones already approved. Revoked signers stay revoked, only
+    /// `reinstate` lifts a revocation.
+    pub fn approve(&mut self, signers: Vec<String>) {
+        for signer in signers {
+            let revoked = self
+                .revoked_signers
+                .iter()
+                .any(|revoked| revoked.key == signer)
+                || self.blocked_signers.contains(&signer);
+            if !revoked && !self.approve_signers.contains(&signer) {
+                self.approve_signers.push(signer);
+            }
+        }
+    }
+
+    /// Approve `signers`, lifting their revocation. Reserved to explicit operator signer changes.
+    pub fn reinstate(&mut self, signers: Vec<String>) {
+        self.revoked_signers
+            .retain(|revoked| !signers.contains(&revoked.key));
+        self.blocked_signers
+            .retain(|blocked| !signers.contains(blocked));
+        self.approve(signers);
+    }
+
+    /// Remove `signer`, its cheques above `nonce` are no longer accepted
+    pub fn revoke(&mut self, signer: &str, nonce: u64) -> bool {
+        let len = self.approve_signers.len();
+        self.approve_signers.retain(|approved| approved != signer);
+        if self.approve_signers.len() == len {
+            return false;
+        }
+
+        self.revoked_signers.push(RevokedSigner {
+            key: signer.to_string(),
+            nonce,
+        });
+        self.prune_revoked();
+        true
+    }
+
+    /// Block the revoked signers whose cheques can no longer be cashed, their nonce being at most
+    /// the withdrawn one, so that cashing only scans the revocations still in effect
+    pub fn prune_revoked(&mut self) {
+        let withdrawn = self.nonce_withdrawl.unwrap_or(0);
+        let (spent, revoked) = std::mem::take(&mut self.revoked_signers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|revoked| revoked.nonce <= withdrawn);
+        self.revoked_signers = revoked;
+        self.blocked_signers
+            .extend(spent.into_iter().map(|revoked| revoked.key));
+    }
+
+    /// Whether `signer` can sign the cheque of `nonce` on behalf of the sender
+    pub fn is_approved(&self, signer: &str, nonce: u64) -> bool {
+        self.approve_signers
+            .iter()
+            .any(|approved| approved == signer)
+            || self
+                .revoked_signers
+                .iter()
+                .any(|revoked| revoked.key == signer && nonce <= revoked.nonce)
+    }
+
+    pub fn withdrawn(&self) -> StdResult<u128> {
+        let withdrawn = match self.face_value {
+            Some(face_value) => Uint128::from(self.nonce_withdrawl.unwrap_or(0))
+                .checked_mul(Uint128::new(face_value))?,
+            None => Uint128::new(self.withdrawn_amount.unwrap_or(0)),
+        };
+        Ok(withdrawn.u128())
+    }
+
+    pub fn remain(&self) -> StdResult<u128> {
+        let remain = Uint128::new(self.max_amount).checked_sub(Uint128::new(self.withdrawn()?))?;
+        Ok(remain.u128())
+    }
+
+    /// Mark the channel settled and return the remainder to refund
+    pub fn settle(&mut self) -> StdResult<u128> {
+        let remain = self.remain()?;
+        self.max_amount = self.withdrawn()?;
+        self.state = ChannelState::Settled;
+        self.reduction = None;
+        self.epoch = self
+            .epoch
+            .checked_add(1)
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Add, self.epoch, 1))?;
+        Ok(remain)
+    }
+}
+
+/// Split the legacy one blob per sender channels into a header and one entry per recipient.
+///
+/// Legacy keys were never checked to be canonical pubkey hashes. A sender key the circuit can not
+/// prove for can never close its channels, so their pending auto releases are dropped and the
+/// operator recovers the remainders through the `ReduceChannel` notice period instead. Recipients
+/// of such keys can never register a payout, their channels are closed through the challenge
+/// period as usual.
+pub fn migrate_payment_channels(storage: &mut dyn Storage) -> StdResult<()> {
+    let denom = CONFIG.load(storage)?.denom;
+    let legacy = LEGACY_PAYMENT_CHANNELS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (sender, chan) in legacy {
+        let len = chan.recipients.len();
+        let recipient_count = u32::try_from(len)
+            .map_err(|_| ConversionOverflowError::new("usize", "u32", len.to_string()))?;
+        PAYMENT_CHANNELS.save(
+            storage,
+            &sender,
+            &PaymentChannel {
+                operator: chan.operator,
+                recipient_count,
+                denom: denom.clone(),
+                pending_operator: None,
+                closes: 0,
+            },
+        )?;
+        let closable = zkp::key_to_field(&sender).is_some();
+        for (recipient_key, recipient) in chan.recipients {
+            let mut recipient = Recipient::from(recipient);
+            if !closable {
+                recipient.state = ChannelState::Open;
+            }
+            recipients().save(
+                storage,
+                (sender.as_str(), recipient_key.as_str()),
+                &recipient,
+            )?;
         }
+        LEGACY_PAYMENT_CHANNELS.remove(storage, sender);
     }
 
-    pub fn remain(&self) -> u128 {
-        self.max_amount - self.nonce_withdrawl.unwrap_or(0) as u128 * self.face_value.unwrap_or(0)
+    Ok(())
+}
+
+/// Accept the denom of the contract wide configuration used before per channel denoms
+pub fn migrate_denoms(storage: &mut dyn Storage) -> StdResult<()> {
+    let denom = CONFIG.load(storage)?.denom;
+    if !ACCEPTED_DENOMS.has(storage, denom.key()) {
+        ACCEPTED_DENOMS.save(storage, denom.key(), &denom)?;
     }
+
+    Ok(())
 }
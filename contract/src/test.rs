@@ -1,25 +1,96 @@
 mod tests {
-    use crate::contract::{execute, instantiate, query};
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::error::ContractError;
+    use crate::handler::CommitmentType;
+    use crate::mock::MockProver;
     use crate::msg::*;
-    use crate::state::{Config, Recipient};
+    use crate::state::{
+        ChannelState, Config, Denom, Fee, LegacyPaymentChannel, LegacyRecipient, PauseFlags,
+        Payout, Recipient, Reduction, CONFIG, LEGACY_PAYMENT_CHANNELS,
+    };
     use cosmwasm_std::{
         coins,
         testing::{mock_dependencies, mock_env, mock_info},
-        Addr, CosmosMsg, Uint128, WasmMsg,
+        Addr, BankMsg, Binary, CanonicalAddr, CosmosMsg, Event, Uint128, WasmMsg,
     };
-    use cosmwasm_std::{from_json, to_json_binary, Api};
+    use cosmwasm_std::{from_json, to_json_binary, to_json_vec, Api, Deps, DepsMut, Storage};
+    use cw2::set_contract_version;
     use cw20::Cw20ExecuteMsg;
+    use std::collections::HashMap;
     const TEST_DENOM: &str = "ugtb";
+    const SENDER_KEY: &str = "1001";
+    const RECIPIENT_KEY1: &str = "2001";
+    const RECIPIENT_KEY2: &str = "2002";
+
+    fn register_payout(deps: DepsMut, prover: &MockProver, recipient_key: &str, address: &str) {
+        let msg = ExecuteMsg::RegisterPayout {
+            recipient_key: recipient_key.to_string(),
+            address: address.to_string(),
+            nonce: 1,
+            commitment: prover.prove(
+                recipient_key,
+                CommitmentType::Payout {
+                    recipient_key,
+                    address,
+                    nonce: 1,
+                },
+            ),
+        };
+        execute(deps, mock_env(), mock_info("relayer", &[]), msg).unwrap();
+    }
+
+    fn cheque(
+        prover: &MockProver,
+        sender_key: &str,
+        recipient_key: &str,
+        nonce: u64,
+        value: Option<u128>,
+    ) -> PaymentCheque {
+        let commitment_type = || CommitmentType::Cheque {
+            sender_key,
+            recipient_key,
+            nonce,
+            value,
+        };
+
+        PaymentCheque {
+            sender_key: sender_key.to_string(),
+            sender_commitment: prover.prove(sender_key, commitment_type()),
+            signer_key: None,
+            recipient_key: recipient_key.to_string(),
+            recipient_commitment: prover.prove(recipient_key, commitment_type()),
+            value,
+            nonce,
+        }
+    }
+
+    /// Face value cheque from `SENDER_KEY` to `RECIPIENT_KEY1` signed by `signer_key`
+    fn delegated_cheque(prover: &MockProver, signer_key: &str, nonce: u64) -> PaymentCheque {
+        let mut res = cheque(prover, SENDER_KEY, RECIPIENT_KEY1, nonce, None);
+        res.signer_key = Some(signer_key.to_string());
+        res.sender_commitment = prover.prove(
+            signer_key,
+            CommitmentType::Cheque {
+                sender_key: SENDER_KEY,
+                recipient_key: RECIPIENT_KEY1,
+                nonce,
+                value: None,
+            },
+        );
+        res
+    }
 
     #[test]
     fn test_init() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
             denom: crate::state::Denom::Cw20(Addr::unchecked("0x01")),
             admin: Some("0x02".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
         let info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
@@ -31,12 +102,14 @@ mod tests {
     #[test]
     fn test_add_payment() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
             denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
             admin: Some("admin_addr".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
         let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
@@ -44,9 +117,9 @@ mod tests {
 
         let add_payment = ExecuteMsg::AddPaymentChan {
             operator: Some("operator".to_string()),
-            chan_key: "sender_pubkey_hash".to_string(),
+            chan_key: SENDER_KEY.to_string(),
             channels: vec![Channel {
-                key: "recipient_pubkey_hash1".to_string(),
+                key: RECIPIENT_KEY1.to_string(),
                 face_value: Some(100),
                 max_amount: 10000,
                 approve_signers: vec![],
@@ -63,223 +136,664 @@ mod tests {
 
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(res.attributes.len(), 1);
+
+        // only the operator can add to an existing payment channel, whatever operator it names
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "funder".to_string(),
+            amount: Uint128::new(15000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+        let info = mock_info("cw20_contract_addr", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOperator {
+                sender: "funder".to_string(),
+                operator: "operator".to_string(),
+            }
+        );
+
+        // surplus is refunded to the cw20 sender
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "operator".to_string(),
+            amount: Uint128::new(15000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+        let info = mock_info("cw20_contract_addr", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let refund_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "cw20_contract_addr".to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "operator".to_string(),
+                amount: Uint128::new(5000),
+            })
+            .unwrap(),
+            funds: vec![],
+        });
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, refund_msg);
+
+        // cw20 channels can only be funded through the cw20 contract
+        let info = mock_info("operator", &coins(10000, TEST_DENOM.to_string()));
+        let err = execute(deps.as_mut(), mock_env(), info, add_payment).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportDenom());
+
+        // top up through the cw20 contract
+        let top_up = ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+        };
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "funder".to_string(),
+            amount: Uint128::new(500),
+            msg: to_json_binary(&top_up).unwrap(),
+        });
+        let info = mock_info("cw20_contract_addr", &[]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                start_after: None,
+                limit: None,
+            },
+        );
+        let payment_chan_resp: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        assert_eq!(payment_chan_resp[0].recipient.max_amount, 20500);
     }
 
     #[test]
-    fn test_cashing() {
+    fn test_add_payment_native() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
-            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
             admin: Some("admin".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
-        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
-        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        // face_value = 100; total_amount = 10000;
+        let info = mock_info("admin", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
         let add_payment = ExecuteMsg::AddPaymentChan {
-            operator: Some("operator".to_string()),
-            chan_key: "sender_pubkey_hash".to_string(),
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
             channels: vec![Channel {
-                key: "recipient_pubkey_hash1".to_string(),
+                key: RECIPIENT_KEY1.to_string(),
                 face_value: Some(100),
                 max_amount: 10000,
                 approve_signers: vec![],
             }],
         };
 
-        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: "sender".to_string(),
-            amount: Uint128::new(10000),
-            msg: to_json_binary(&add_payment).unwrap(),
-        });
+        let info = mock_info("sender", &coins(10000, "other"));
+        let err = execute(deps.as_mut(), mock_env(), info, add_payment.clone()).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportDenom());
 
-        info.sender = Addr::unchecked("cw20_contract_addr");
-        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        assert_eq!(res.attributes.len(), 1);
+        let info = mock_info("sender", &coins(9999, TEST_DENOM));
+        let err = execute(deps.as_mut(), mock_env(), info, add_payment.clone()).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientFund);
 
-        let add_payment = ExecuteMsg::AddPaymentChan {
-            operator: Some("operator".to_string()),
-            chan_key: "sender_pubkey_hash".to_string(),
+        // keys the circuit can not prove for
+        let with_key = |chan_key: &str, key: &str, signer: &str| ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: chan_key.to_string(),
             channels: vec![Channel {
-                key: "recipient_pubkey_hash2".to_string(),
-                face_value: Some(200),
-                max_amount: 20000,
-                approve_signers: vec![],
+                key: key.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec![signer.to_string()],
             }],
         };
+        for (msg, key) in [
+            (
+                with_key("sender_pubkey_hash", RECIPIENT_KEY1, "3001"),
+                "sender_pubkey_hash",
+            ),
+            (with_key(SENDER_KEY, "02001", "3001"), "02001"),
+            (with_key(SENDER_KEY, RECIPIENT_KEY1, "signer"), "signer"),
+        ] {
+            let info = mock_info("sender", &coins(10000, TEST_DENOM));
+            let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::InvalidKey {
+                    key: key.to_string()
+                }
+            );
+        }
 
-        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: "sender".to_string(),
-            amount: Uint128::new(20000),
-            msg: to_json_binary(&add_payment).unwrap(),
-        });
+        // a mistyped operator would orphan the channel
+        let with_operator = ExecuteMsg::AddPaymentChan {
+            operator: Some("Operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![],
+        };
+        let info = mock_info("sender", &coins(10000, TEST_DENOM));
+        let err = execute(deps.as_mut(), mock_env(), info, with_operator).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
 
-        info.sender = Addr::unchecked("cw20_contract_addr");
+        let info = mock_info("sender", &coins(10000, TEST_DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, add_payment.clone()).unwrap();
+        assert_eq!(res.attributes.len(), 1);
+        assert!(res.messages.is_empty());
 
-        let _ = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        // surplus is refunded to the funder
+        let info = mock_info("sender", &coins(12000, TEST_DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(2000, TEST_DENOM),
+            })
+        );
 
         let res = query(
             deps.as_ref(),
             mock_env(),
             QueryMsg::PaymentChan {
-                sender_pubkey_hash: "sender_pubkey_hash".to_string(),
-                recipient_pubkey_hash: None,
-                page: None,
-                size: None,
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                start_after: None,
+                limit: None,
             },
         );
+        let payment_chan_resp: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        assert_eq!(payment_chan_resp[0].recipient.max_amount, 20000);
 
-        let payment_chan_resp: Option<Vec<Recipient>> = from_json(res.unwrap()).unwrap();
-
-        assert_eq!(payment_chan_resp.unwrap().len(), 2);
-
+        // cashing pays out in the native denom
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "cashier");
         let msg = ExecuteMsg::Cashing {
-            recipient_key: "recipient_pubkey_hash1".to_string(),
-            cheques: vec![PaymentCheque {
-                sender_key: "sender_pubkey_hash".to_string(),
-                sender_commitment: vec![],
-                recipient_key: "recipient_pubkey_hash1".to_string(),
-                recipient_commitment: vec![],
-                value: None,
-                nonce: 3,
-            }],
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, None)],
         };
-
-        info.sender = Addr::unchecked("cashing_account");
-
-        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(res.messages.len(), 1);
-        // 2 cheque amount = 2 * 100 = 200
-        let refund_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "cw20_contract_addr".to_string(),
-            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: "cashing_account".to_string(),
-                amount: Uint128::new(300),
+        let res = execute(deps.as_mut(), mock_env(), mock_info("cashier", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "cashier".to_string(),
+                amount: coins(200, TEST_DENOM),
             })
-            .unwrap(),
-            funds: vec![],
-        });
-        assert_eq!(res.messages[0].msg, refund_msg);
+        );
     }
 
     #[test]
-    fn test_update_config() {
+    fn test_multi_denom() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
-            denom: crate::state::Denom::Cw20(Addr::unchecked("0x01")),
-            admin: Some("0x02".to_string()),
+            denom: Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
-        let info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
-        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
 
-        let mut config_res: Config =
-            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        let update_denoms =
+            |add: Vec<Denom>, remove: Vec<Denom>| ExecuteMsg::UpdateDenoms { add, remove };
+        let usdc = Denom::Native("uusdc".to_string());
+        let cw20 = Denom::Cw20(Addr::unchecked("cw20_contract_addr"));
+
+        let msg = update_denoms(vec![usdc.clone()], vec![]);
+        let err = execute(deps.as_mut(), mock_env(), mock_info("someone", &[]), msg).unwrap_err();
         assert_eq!(
-            config_res.denom,
-            crate::state::Denom::Cw20(Addr::unchecked("0x01"))
+            err,
+            ContractError::NotOwner {
+                sender: "someone".to_string(),
+                owner: "admin".to_string(),
+            }
+        );
+
+        let msg = update_denoms(vec![usdc.clone(), cw20.clone()], vec![]);
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AcceptedDenoms {}).unwrap();
+        let denoms: Vec<Denom> = from_json(res).unwrap();
+        assert_eq!(denoms.len(), 3);
+        assert!(denoms.contains(&usdc) && denoms.contains(&cw20));
+
+        let add_payment = |sender_key: &str| ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: sender_key.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment(SENDER_KEY)).unwrap();
+        let info = mock_info("sender", &coins(1000, "uusdc"));
+        execute(deps.as_mut(), mock_env(), info, add_payment("1002")).unwrap();
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "sender".to_string(),
+            amount: Uint128::new(1000),
+            msg: to_json_binary(&add_payment("1003")).unwrap(),
+        });
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("cw20_contract_addr", &[]),
+            msg,
+        )
+        .unwrap();
+        // the cw20 sender, not the token contract, operates the channel by default
+        let msg = ExecuteMsg::TransferOperator {
+            chan_key: "1003".to_string(),
+            new_operator: "new_operator".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("sender", &[]), msg).unwrap();
+
+        // recipient channels of a sender share its denom
+        let info = mock_info("sender", &coins(1000, "uusdc"));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            add_payment(SENDER_KEY),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::DenomMismatch {
+                denom: TEST_DENOM.to_string()
+            }
         );
+        let top_up = ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, top_up).unwrap_err();
         assert_eq!(
-            config_res.owner,
-            deps.api.addr_canonicalize("admin").unwrap()
+            err,
+            ContractError::DenomMismatch {
+                denom: TEST_DENOM.to_string()
+            }
         );
-        assert_eq!(config_res.auto_release_time, 100);
-        assert_eq!(config_res.max_recipient, 1024);
 
-        let update_config_msg = ExecuteMsg::UpdateConfig {
-            owner: Some("new_owner".to_string()),
-            auto_release_time: Some(2000000),
-            max_recipient: Some(10),
+        // each cheque is paid out in the denom of its channel
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: ["1001", "1002", "1003"]
+                .into_iter()
+                .map(|sender_key| cheque(&prover, sender_key, RECIPIENT_KEY1, 2, None))
+                .collect(),
         };
-
-        let res = execute(deps.as_mut(), mock_env(), info, update_config_msg);
-        config_res =
-            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
-        assert_eq!(res.unwrap().attributes.len(), 1);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg).unwrap();
         assert_eq!(
-            config_res.denom,
-            crate::state::Denom::Cw20(Addr::unchecked("0x01"))
+            res.messages
+                .into_iter()
+                .map(|msg| msg.msg)
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "recipient_addr".to_string(),
+                    amount: coins(200, TEST_DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "recipient_addr".to_string(),
+                    amount: coins(200, "uusdc"),
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "cw20_contract_addr".to_string(),
+                    msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: "recipient_addr".to_string(),
+                        amount: Uint128::new(200),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
         );
-        assert_eq!(config_res.auto_release_time, 2000000);
-        assert_eq!(config_res.max_recipient, 10);
+
+        // removed denoms can not fund new channels but existing ones still pay out
+        let msg = update_denoms(vec![], vec![usdc]);
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        let info = mock_info("sender", &coins(1000, "uusdc"));
+        let err = execute(deps.as_mut(), mock_env(), info, add_payment("1004")).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportDenom());
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, "1002", RECIPIENT_KEY1, 3, None)],
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg).unwrap();
         assert_eq!(
-            config_res.owner,
-            deps.api.addr_canonicalize("new_owner").unwrap()
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "recipient_addr".to_string(),
+                amount: coins(100, "uusdc"),
+            })
         );
-        let not_admin_info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+    }
 
-        let update_config_msg = ExecuteMsg::UpdateConfig {
-            owner: Some("new_owner".to_string()),
-            auto_release_time: Some(2000000),
-            max_recipient: Some(10),
+    #[test]
+    fn test_top_up() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
-        let res = execute(deps.as_mut(), mock_env(), not_admin_info, update_config_msg);
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
 
-        assert!(res.is_err());
+        let add_payment =
+            |face_value: Option<u128>, approve_signers: Vec<String>| ExecuteMsg::AddPaymentChan {
+                operator: None,
+                chan_key: SENDER_KEY.to_string(),
+                channels: vec![Channel {
+                    key: RECIPIENT_KEY1.to_string(),
+                    face_value,
+                    max_amount: 1000,
+                    approve_signers,
+                }],
+            };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        let msg = add_payment(Some(100), vec!["3001".to_string()]);
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        // the face value of an existing channel can not be changed
+        for face_value in [Some(200), None] {
+            let msg = add_payment(face_value, vec![]);
+            let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::FaceValueMismatch {
+                    recipient_key: RECIPIENT_KEY1.to_string()
+                }
+            );
+        }
+
+        // new signers are merged without duplicates
+        let msg = add_payment(Some(100), vec!["3001".to_string(), "3002".to_string()]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let top_up = |recipient_key: &str| ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: recipient_key.to_string(),
+        };
+
+        let info = mock_info("anyone", &coins(500, TEST_DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, top_up(RECIPIENT_KEY1)).unwrap();
+        assert!(res.messages.is_empty());
+
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, top_up(RECIPIENT_KEY1)).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientFund);
+
+        let info = mock_info("anyone", &coins(500, "other"));
+        let err = execute(deps.as_mut(), mock_env(), info, top_up(RECIPIENT_KEY1)).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportDenom());
+
+        let info = mock_info("anyone", &coins(500, TEST_DENOM));
+        let err = execute(deps.as_mut(), mock_env(), info, top_up(RECIPIENT_KEY2)).unwrap_err();
+        assert_eq!(err, ContractError::RecipientNotFound);
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                start_after: None,
+                limit: None,
+            },
+        );
+        let payment_chan_resp: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        let recipient = &payment_chan_resp[0].recipient;
+        assert_eq!(recipient.max_amount, 2500);
+        assert_eq!(recipient.face_value, Some(100));
+        assert_eq!(recipient.approve_signers, vec!["3001", "3002"]);
     }
 
     #[test]
-    fn test_close_payment_chan() {
+    fn test_max_recipient() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
-            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let channel = |key: &str| Channel {
+            key: key.to_string(),
+            face_value: Some(100),
+            max_amount: 10000,
+            approve_signers: vec![],
+        };
+        let add_payment = |channels: Vec<Channel>| ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels,
+        };
+        let info = mock_info("sender", &coins(20000, TEST_DENOM));
+
+        let msg = add_payment(vec![channel(RECIPIENT_KEY1), channel(RECIPIENT_KEY2)]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ExceedRecipientNum);
+
+        let msg = add_payment(vec![channel(RECIPIENT_KEY1)]);
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = add_payment(vec![channel(RECIPIENT_KEY2)]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ExceedRecipientNum);
+
+        // funding an existing recipient does not count
+        let msg = add_payment(vec![channel(RECIPIENT_KEY1)]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let count: u32 = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::RecipientCount {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_payment_chan_pagination() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
             admin: Some("admin".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
-        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
-        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        // face_value = 100; total_amount = 10000;
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let keys = (0..40).map(|i| (3000 + i).to_string()).collect::<Vec<_>>();
         let add_payment = ExecuteMsg::AddPaymentChan {
-            operator: Some("operator".to_string()),
-            chan_key: "sender_pubkey_hash".to_string(),
-            channels: vec![Channel {
-                key: "recipient_pubkey_hash1".to_string(),
-                face_value: Some(100),
-                max_amount: 10000,
-                approve_signers: vec![],
-            }],
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: keys
+                .iter()
+                .rev()
+                .map(|key| Channel {
+                    key: key.clone(),
+                    face_value: Some(100),
+                    max_amount: 100,
+                    approve_signers: vec![],
+                })
+                .collect(),
         };
+        let info = mock_info("sender", &coins(4000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
 
-        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: "sender".to_string(),
-            amount: Uint128::new(10000),
-            msg: to_json_binary(&add_payment).unwrap(),
-        });
+        let page = |start_after: Option<String>, limit: Option<u32>| {
+            let res = query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::PaymentChan {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                    recipient_pubkey_hash: None,
+                    start_after,
+                    limit,
+                },
+            );
+            from_json::<Vec<RecipientInfo>>(res.unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|info| info.key)
+                .collect::<Vec<_>>()
+        };
 
-        info.sender = Addr::unchecked("cw20_contract_addr");
-        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        assert_eq!(res.attributes.len(), 1);
+        assert_eq!(page(None, None), keys[..10]);
+        assert_eq!(page(Some(keys[9].clone()), Some(5)), keys[10..15]);
+        // limit is capped
+        assert_eq!(page(None, Some(100)), keys[..30]);
+        assert_eq!(page(Some(keys[29].clone()), Some(30)), keys[30..]);
+        assert!(page(Some(keys[39].clone()), None).is_empty());
+    }
 
-        let close_msg = ExecuteMsg::ClosePaymentChan {
-            chan_key: "sender_pubkey_hash".to_string(),
-            commitment: vec![],
-            channels: vec![("recipient_pubkey_hash1".to_string(), vec![])],
+    #[test]
+    fn test_channels_by_recipient() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
-        info.sender = Addr::unchecked("operator");
-        let res = execute(deps.as_mut(), mock_env(), info.clone(), close_msg).unwrap();
-        assert_eq!(res.messages.len(), 1);
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let senders = (0..12).map(|i| (1100 + i).to_string()).collect::<Vec<_>>();
+        for sender in senders.iter() {
+            let add_payment = ExecuteMsg::AddPaymentChan {
+                operator: None,
+                chan_key: sender.clone(),
+                channels: [RECIPIENT_KEY1, RECIPIENT_KEY2]
+                    .iter()
+                    .map(|key| Channel {
+                        key: key.to_string(),
+                        face_value: Some(100),
+                        max_amount: 1000,
+                        approve_signers: vec![],
+                    })
+                    .collect(),
+            };
+            let info = mock_info("sender", &coins(2000, TEST_DENOM));
+            execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+        }
+
+        fn page(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> Vec<String> {
+            let res = query(
+                deps,
+                mock_env(),
+                QueryMsg::ChannelsByRecipient {
+                    recipient_key: RECIPIENT_KEY1.to_string(),
+                    start_after,
+                    limit,
+                },
+            );
+            from_json::<Vec<ChannelInfo>>(res.unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|info| info.sender_key)
+                .collect()
+        }
+
+        assert_eq!(page(deps.as_ref(), None, None), senders[..10]);
+        assert_eq!(
+            page(deps.as_ref(), Some(senders[9].clone()), None),
+            senders[10..]
+        );
+        assert_eq!(
+            page(deps.as_ref(), Some(senders[3].clone()), Some(2)),
+            senders[4..6]
+        );
+
+        // settled channels stay indexed with their final state
+        let close_msg = ExecuteMsg::ClosePaymentChan {
+            chan_key: senders[0].clone(),
+            commitment: prover.prove(
+                &senders[0],
+                CommitmentType::CloseChannel {
+                    sender_key: &senders[0],
+                    closes: 0,
+                },
+            ),
+            channels: vec![(
+                RECIPIENT_KEY1.to_string(),
+                prover.prove(
+                    RECIPIENT_KEY1,
+                    CommitmentType::CloseConsent {
+                        sender_key: &senders[0],
+                        recipient_key: RECIPIENT_KEY1,
+                        epoch: 0,
+                        withdrawn: 0,
+                    },
+                ),
+            )],
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("sender", &[]),
+            close_msg,
+        )
+        .unwrap();
+        assert_eq!(page(deps.as_ref(), None, Some(30)), senders);
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ChannelsByRecipient {
+                recipient_key: RECIPIENT_KEY1.to_string(),
+                start_after: None,
+                limit: Some(1),
+            },
+        );
+        let channels: Vec<ChannelInfo> = from_json(res.unwrap()).unwrap();
+        assert_eq!(channels[0].recipient.state, ChannelState::Settled);
     }
 
     #[test]
-    fn test_add_signer() {
+    fn test_cashing() {
         let mut deps = mock_dependencies();
+        let prover = MockProver::new();
 
         let msg = InstantiateMsg {
             denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
             admin: Some("admin".to_string()),
             auto_release_time: 100,
             max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
         };
 
         let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
@@ -287,9 +801,9 @@ mod tests {
         // face_value = 100; total_amount = 10000;
         let add_payment = ExecuteMsg::AddPaymentChan {
             operator: Some("operator".to_string()),
-            chan_key: "sender_pubkey_hash".to_string(),
+            chan_key: SENDER_KEY.to_string(),
             channels: vec![Channel {
-                key: "recipient_pubkey_hash1".to_string(),
+                key: RECIPIENT_KEY1.to_string(),
                 face_value: Some(100),
                 max_amount: 10000,
                 approve_signers: vec![],
@@ -306,14 +820,2511 @@ mod tests {
         let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
         assert_eq!(res.attributes.len(), 1);
 
-        let add_signer = ExecuteMsg::AddSigner {
-            chan_key: "sender_pubkey_hash".to_string(),
-            recipient_key: "recipient_pubkey_hash1".to_string(),
-            signers: vec!["new_signer".to_string()],
-        };
-
-        info.sender = Addr::unchecked("operator");
-        let res = execute(deps.as_mut(), mock_env(), info, add_signer).unwrap();
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY2.to_string(),
+                face_value: Some(200),
+                max_amount: 20000,
+                approve_signers: vec![],
+            }],
+        };
+
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "operator".to_string(),
+            amount: Uint128::new(20000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+
+        info.sender = Addr::unchecked("cw20_contract_addr");
+
+        let _ = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: None,
+                start_after: None,
+                limit: None,
+            },
+        );
+
+        let payment_chan_resp: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+
+        assert_eq!(payment_chan_resp.len(), 2);
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None)],
+        };
+
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+        // cheques can be relayed by any account
+        info.sender = Addr::unchecked("cashing_account");
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        // 2 cheque amount = 2 * 100 = 200
+        let refund_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "cw20_contract_addr".to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "recipient_addr".to_string(),
+                amount: Uint128::new(300),
+            })
+            .unwrap(),
+            funds: vec![],
+        });
+        assert_eq!(res.messages[0].msg, refund_msg);
+
+        // the same cheque can not be cashed twice
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, ContractError::NonceNotIncreasing);
+    }
+
+    #[test]
+    fn test_cashing_any_value() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: None,
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(10000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "cashier");
+        let info = mock_info("cashier", &[]);
+        let cashing = |cheque: PaymentCheque| ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque],
+        };
+
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 1, Some(3000)));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "cashier".to_string(),
+                amount: coins(3000, TEST_DENOM),
+            })
+        );
+
+        // face value cheque on an any value channel
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, None));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidChequeValue);
+
+        // cumulative amount below the withdrawn amount
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, Some(2000)));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidChequeValue);
+
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, Some(10001)));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::Overdraw);
+
+        // only the increase since the last cashing is paid
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, Some(5000)));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "cashier".to_string(),
+                amount: coins(2000, TEST_DENOM),
+            })
+        );
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                start_after: None,
+                limit: None,
+            },
+        );
+        let recipients: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        let recipient = &recipients[0].recipient;
+        assert_eq!(recipient.withdrawn_amount, Some(5000));
+        assert_eq!(recipient.remain().unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_cashing_approved_signer() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec!["3001".to_string()],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let delegated = |signer_key: &str, nonce: u64| delegated_cheque(&prover, signer_key, nonce);
+        let cashing = |cheque: PaymentCheque| ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque],
+        };
+        let relayer = mock_info("relayer", &[]);
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            relayer.clone(),
+            cashing(delegated("3002", 1)),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnapprovedSigner {
+                signer_key: "3002".to_string()
+            }
+        );
+
+        // the proof must come from the claimed signer
+        let mut forged = cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 1, None);
+        forged.signer_key = Some("3001".to_string());
+        let err = execute(deps.as_mut(), mock_env(), relayer.clone(), cashing(forged)).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            relayer.clone(),
+            cashing(delegated("3001", 2)),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "recipient_addr".to_string(),
+                amount: coins(200, TEST_DENOM),
+            })
+        );
+
+        // the sender key itself can always sign
+        let msg = cashing(cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None));
+        execute(deps.as_mut(), mock_env(), relayer.clone(), msg).unwrap();
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3002".to_string()],
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("sender", &[]),
+            add_signer,
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            relayer,
+            cashing(delegated("3002", 4)),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cashing_fee() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let update_fee = |bps: u16| ExecuteMsg::UpdateConfig {
+            auto_release_time: None,
+            max_recipient: None,
+            fee: Some(Fee {
+                bps,
+                // minimums are in base units of each denom
+                min_amounts: vec![
+                    (Denom::Native(TEST_DENOM.to_string()), 5),
+                    (Denom::Cw20(Addr::unchecked("cw20_contract_addr")), 5000),
+                ],
+                collector: Addr::unchecked("fee_collector"),
+            }),
+        };
+        let admin = mock_info("admin", &[]);
+        let err =
+            execute(deps.as_mut(), mock_env(), admin.clone(), update_fee(10_001)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidFee { max_bps: 10_000 });
+        execute(deps.as_mut(), mock_env(), admin, update_fee(100)).unwrap();
+
+        let estimate = |deps: Deps, denom: &Denom, amount: u128| -> FeeEstimateResponse {
+            let msg = QueryMsg::FeeEstimate {
+                denom: denom.clone(),
+                amount,
+            };
+            from_json(query(deps, mock_env(), msg).unwrap()).unwrap()
+        };
+        let native = Denom::Native(TEST_DENOM.to_string());
+        assert_eq!(
+            estimate(deps.as_ref(), &native, 1000),
+            FeeEstimateResponse {
+                fee: 10,
+                payout: 990
+            }
+        );
+        // the flat minimum of the denom applies to small amounts but never exceeds them
+        assert_eq!(estimate(deps.as_ref(), &native, 100).fee, 5);
+        assert_eq!(
+            estimate(deps.as_ref(), &native, 3),
+            FeeEstimateResponse { fee: 3, payout: 0 }
+        );
+        let cw20 = Denom::Cw20(Addr::unchecked("cw20_contract_addr"));
+        assert_eq!(estimate(deps.as_ref(), &cw20, 100_000).fee, 5000);
+        let other = Denom::Native("other".to_string());
+        assert_eq!(estimate(deps.as_ref(), &other, 100).fee, 1);
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, None)],
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages
+                .iter()
+                .map(|msg| msg.msg.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "recipient_addr".to_string(),
+                    amount: coins(195, TEST_DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "fee_collector".to_string(),
+                    amount: coins(5, TEST_DENOM),
+                }),
+            ]
+        );
+        assert!(res.events.contains(
+            &Event::new("fee_collected")
+                .add_attribute("denom", TEST_DENOM)
+                .add_attribute("amount", "5")
+                .add_attribute("collector", "fee_collector")
+        ));
+    }
+
+    #[test]
+    fn test_cashing_verify() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![
+                Channel {
+                    key: RECIPIENT_KEY1.to_string(),
+                    face_value: Some(100),
+                    max_amount: 10000,
+                    approve_signers: vec![],
+                },
+                Channel {
+                    key: RECIPIENT_KEY2.to_string(),
+                    face_value: Some(100),
+                    max_amount: 10000,
+                    approve_signers: vec![],
+                },
+            ],
+        };
+
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "sender".to_string(),
+            amount: Uint128::new(20000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+
+        info.sender = Addr::unchecked("cw20_contract_addr");
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+        info.sender = Addr::unchecked("cashing_account");
+
+        // cheque of another recipient
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY2, 3, None)],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        // duplicate sender in one batch
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![
+                cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None),
+                cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 4, None),
+            ],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::DuplicateCheque {
+                sender_key: SENDER_KEY.to_string()
+            }
+        );
+
+        // zero nonce
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 0, None)],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::NonceNotIncreasing);
+
+        // commitment signed for another nonce
+        let mut forged = cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None);
+        forged.nonce = 50;
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![forged],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        // cheque signed for another deployment, or for the same address on another chain
+        let mut other_contract = mock_env();
+        other_contract.contract.address = Addr::unchecked("other_contract");
+        let mut other_chain = mock_env();
+        other_chain.block.chain_id = "other-chain".to_string();
+        for env in [other_contract, other_chain] {
+            let commitment_type = || CommitmentType::Cheque {
+                sender_key: SENDER_KEY,
+                recipient_key: RECIPIENT_KEY1,
+                nonce: 3,
+                value: None,
+            };
+            let mut replayed = cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None);
+            replayed.sender_commitment = prover.prove_for(&env, SENDER_KEY, commitment_type());
+            replayed.recipient_commitment =
+                prover.prove_for(&env, RECIPIENT_KEY1, commitment_type());
+            let msg = ExecuteMsg::Cashing {
+                recipient_key: RECIPIENT_KEY1.to_string(),
+                cheques: vec![replayed],
+            };
+            let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+            assert_eq!(err, ContractError::ChecksVerifyFailed);
+        }
+
+        // cheque worth more than the channel balance
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 101, None)],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::Overdraw);
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, u64::MAX, None)],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::Overdraw);
+
+        // recipient commitment missing
+        let mut unsigned = cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None);
+        unsigned.recipient_commitment = vec![];
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![unsigned],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+    }
+
+    #[test]
+    fn test_register_payout() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(10000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let info = mock_info("relayer", &[]);
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 1, None)],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::PayoutNotRegistered);
+
+        let register = |address: &str, signer: &str, nonce: u64| ExecuteMsg::RegisterPayout {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            address: address.to_string(),
+            nonce,
+            commitment: prover.prove(
+                signer,
+                CommitmentType::Payout {
+                    recipient_key: RECIPIENT_KEY1,
+                    address,
+                    nonce,
+                },
+            ),
+        };
+
+        // only the recipient can bind its payout address
+        let msg = register("relayer", SENDER_KEY, 1);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        let msg = register("recipient_addr", RECIPIENT_KEY1, 1);
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        // old registrations can not be replayed
+        let msg = register("recipient_addr", RECIPIENT_KEY1, 1);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::NonceNotIncreasing);
+
+        let msg = register("new_recipient_addr", RECIPIENT_KEY1, 2);
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let payout: Option<Payout> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Payout {
+                    recipient_pubkey_hash: RECIPIENT_KEY1.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            payout,
+            Some(Payout {
+                address: Addr::unchecked("new_recipient_addr"),
+                nonce: 2,
+            })
+        );
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 1, None)],
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "new_recipient_addr".to_string(),
+                amount: coins(100, TEST_DENOM),
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_config() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("0x01")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        // the admin of the message owns the contract, not its sender
+        let info = mock_info("creator", &coins(0, TEST_DENOM.to_string()));
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let mut config_res: Config =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(
+            config_res.denom,
+            crate::state::Denom::Cw20(Addr::unchecked("0x01"))
+        );
+        assert_eq!(
+            config_res.owner,
+            deps.api.addr_canonicalize("admin").unwrap()
+        );
+        assert_eq!(config_res.auto_release_time, 100);
+        assert_eq!(config_res.max_recipient, 1024);
+
+        let update_config_msg = ExecuteMsg::UpdateConfig {
+            auto_release_time: Some(2000000),
+            max_recipient: Some(10),
+            fee: None,
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            update_config_msg.clone(),
+        );
+        assert!(res.is_err());
+
+        let info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+        let res = execute(deps.as_mut(), mock_env(), info, update_config_msg);
+        config_res =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.unwrap().attributes.len(), 1);
+        assert_eq!(
+            config_res.denom,
+            crate::state::Denom::Cw20(Addr::unchecked("0x01"))
+        );
+        assert_eq!(config_res.auto_release_time, 2000000);
+        assert_eq!(config_res.max_recipient, 10);
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: None,
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let owner = |deps: Deps| -> CanonicalAddr {
+            let config: Config =
+                from_json(query(deps, mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+            config.owner
+        };
+        let propose = |new_owner: &str, expires_in: Option<u64>| ExecuteMsg::ProposeOwner {
+            new_owner: new_owner.to_string(),
+            expires_in,
+        };
+        let admin = mock_info("admin", &[]);
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            propose("new_owner", None),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOwner {
+                sender: "new_owner".to_string(),
+                owner: "admin".to_string(),
+            }
+        );
+
+        // nothing changes until the proposed owner accepts
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            propose("typo_owner", None),
+        )
+        .unwrap();
+        assert_eq!(
+            owner(deps.as_ref()),
+            deps.api.addr_canonicalize("admin").unwrap()
+        );
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            ExecuteMsg::AcceptOwner {},
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotPendingOwner {
+                sender: "new_owner".to_string()
+            }
+        );
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::CancelOwnerProposal {},
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::CancelOwnerProposal {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoPendingOwner);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("typo_owner", &[]),
+            ExecuteMsg::AcceptOwner {},
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotPendingOwner {
+                sender: "typo_owner".to_string()
+            }
+        );
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            propose("new_owner", Some(u64::MAX)),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            propose("new_owner", Some(50)),
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(51);
+        let err = execute(
+            deps.as_mut(),
+            env,
+            mock_info("new_owner", &[]),
+            ExecuteMsg::AcceptOwner {},
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OwnerProposalExpired {
+                expires_at: mock_env().block.time.seconds() + 50
+            }
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            ExecuteMsg::AcceptOwner {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.events,
+            vec![Event::new("owner_transferred")
+                .add_attribute("old_owner", "admin")
+                .add_attribute("owner", "new_owner")]
+        );
+        assert_eq!(
+            owner(deps.as_ref()),
+            deps.api.addr_canonicalize("new_owner").unwrap()
+        );
+        assert!(execute(deps.as_mut(), mock_env(), admin, propose("admin", None)).is_err());
+    }
+
+    #[test]
+    fn test_close_payment_chan() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        // face_value = 100; total_amount = 10000;
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "sender".to_string(),
+            amount: Uint128::new(10000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+
+        info.sender = Addr::unchecked("cw20_contract_addr");
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert_eq!(res.attributes.len(), 1);
+
+        // garbage commitment is rejected
+        let close_msg = ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: vec![1, 2, 3],
+            channels: vec![(RECIPIENT_KEY1.to_string(), vec![])],
+        };
+
+        info.sender = Addr::unchecked("operator");
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), close_msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        // proof signed by another key is rejected
+        let close_msg = ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                RECIPIENT_KEY1,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes: 0,
+                },
+            ),
+            channels: vec![(RECIPIENT_KEY1.to_string(), vec![])],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), close_msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        let close_msg = ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                SENDER_KEY,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes: 0,
+                },
+            ),
+            channels: vec![(
+                RECIPIENT_KEY1.to_string(),
+                prover.prove(
+                    RECIPIENT_KEY1,
+                    CommitmentType::CloseConsent {
+                        sender_key: SENDER_KEY,
+                        recipient_key: RECIPIENT_KEY1,
+                        epoch: 0,
+                        withdrawn: 0,
+                    },
+                ),
+            )],
+        };
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), close_msg.clone()).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let refund_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "cw20_contract_addr".to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "operator".to_string(),
+                amount: Uint128::new(10000),
+            })
+            .unwrap(),
+            funds: vec![],
+        });
+        assert_eq!(res.messages[0].msg, refund_msg);
+
+        // the sender close request can not be replayed
+        let err = execute(deps.as_mut(), mock_env(), info, close_msg).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+    }
+
+    #[test]
+    fn test_close_payment_chan_challenge() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info.clone(), add_payment.clone()).unwrap();
+
+        let close_msg = |closes: u64, recipient_commitment: Vec<u8>| ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                SENDER_KEY,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes,
+                },
+            ),
+            channels: vec![(RECIPIENT_KEY1.to_string(), recipient_commitment)],
+        };
+        let recipient = |deps: Deps| {
+            let res = query(
+                deps,
+                mock_env(),
+                QueryMsg::PaymentChan {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                    recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                    start_after: None,
+                    limit: None,
+                },
+            );
+            from_json::<Vec<RecipientInfo>>(res.unwrap()).unwrap()[0]
+                .recipient
+                .clone()
+        };
+        let cashing = |nonce: u64| ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, nonce, None)],
+        };
+
+        // without the recipient consent the channel enters its challenge period
+        let env = mock_env();
+        let deadline = env.block.time.seconds() + 100;
+        let operator = mock_info("sender", &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(0, vec![]),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            recipient(deps.as_ref()).state,
+            ChannelState::Closing {
+                deadline,
+                paused_for: 0,
+            }
+        );
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(1, vec![]),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::ChallengePeriod { deadline });
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            add_payment.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::ChannelNotOpen);
+        let top_up = ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+        };
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), top_up).unwrap_err();
+        assert_eq!(err, ContractError::ChannelNotOpen);
+
+        // the recipient cashes its latest cheque during the challenge period
+        let relayer = mock_info("relayer", &[]);
+        let res = execute(deps.as_mut(), env.clone(), relayer.clone(), cashing(3)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "recipient_addr".to_string(),
+                amount: coins(300, TEST_DENOM),
+            })
+        );
+
+        // the remainder is refunded once the deadline has passed
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(1, vec![]),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(700, TEST_DENOM),
+            })
+        );
+        let settled = recipient(deps.as_ref());
+        assert_eq!(settled.state, ChannelState::Settled);
+        assert_eq!(settled.remain().unwrap(), 0);
+
+        let err = execute(deps.as_mut(), env.clone(), relayer.clone(), cashing(4)).unwrap_err();
+        assert_eq!(err, ContractError::ChannelSettled);
+
+        // settling again refunds nothing
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(2, vec![]),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+
+        // funding a settled channel opens it again without resetting its nonce
+        execute(deps.as_mut(), env.clone(), info, add_payment).unwrap();
+        let reopened = recipient(deps.as_ref());
+        assert_eq!(reopened.state, ChannelState::Open);
+        assert_eq!(reopened.remain().unwrap(), 1000);
+
+        let err = execute(deps.as_mut(), env.clone(), relayer.clone(), cashing(3)).unwrap_err();
+        assert_eq!(err, ContractError::NonceNotIncreasing);
+        let res = execute(deps.as_mut(), env.clone(), relayer, cashing(5)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "recipient_addr".to_string(),
+                amount: coins(200, TEST_DENOM),
+            })
+        );
+
+        // a consent signed for the previous lifetime can not be replayed
+        let stale_commitment = prover.prove(
+            RECIPIENT_KEY1,
+            CommitmentType::CloseConsent {
+                sender_key: SENDER_KEY,
+                recipient_key: RECIPIENT_KEY1,
+                epoch: 0,
+                withdrawn: 500,
+            },
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(3, stale_commitment),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            recipient(deps.as_ref()).state,
+            ChannelState::Closing {
+                deadline: env.block.time.seconds() + 100,
+                paused_for: 0,
+            }
+        );
+
+        // the recipient consent settles immediately
+        let recipient_commitment = prover.prove(
+            RECIPIENT_KEY1,
+            CommitmentType::CloseConsent {
+                sender_key: SENDER_KEY,
+                recipient_key: RECIPIENT_KEY1,
+                epoch: 1,
+                withdrawn: 500,
+            },
+        );
+        let res = execute(
+            deps.as_mut(),
+            env,
+            operator,
+            close_msg(4, recipient_commitment),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(800, TEST_DENOM),
+            })
+        );
+        assert_eq!(recipient(deps.as_ref()).state, ChannelState::Settled);
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info.clone(), add_payment.clone()).unwrap();
+
+        let all = PauseFlags {
+            deposits: true,
+            cashing: true,
+            closing: true,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("sender", &[]),
+            ExecuteMsg::Pause { flags: all },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOwner {
+                sender: "sender".to_string(),
+                owner: "admin".to_string(),
+            }
+        );
+        let admin = mock_info("admin", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::Pause { flags: all },
+        )
+        .unwrap();
+
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), add_payment).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Paused {
+                operation: "Deposits".to_string()
+            }
+        );
+        let top_up = ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, top_up).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Paused {
+                operation: "Deposits".to_string()
+            }
+        );
+        let cashing = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 2, None)],
+        };
+        let relayer = mock_info("relayer", &[]);
+        let err = execute(deps.as_mut(), mock_env(), relayer.clone(), cashing.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Paused {
+                operation: "Cashing".to_string()
+            }
+        );
+        // the recipient consent is ignored, reductions wait for the notice period
+        let reduce = ExecuteMsg::ReduceChannel {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            amount: 100,
+            commitment: Some(prover.prove(
+                RECIPIENT_KEY1,
+                CommitmentType::ReduceChannel {
+                    sender_key: SENDER_KEY,
+                    recipient_key: RECIPIENT_KEY1,
+                    max_amount: 1000,
+                    amount: 100,
+                    reductions: 0,
+                },
+            )),
+        };
+        let operator = mock_info("sender", &[]);
+        let res = execute(deps.as_mut(), mock_env(), operator.clone(), reduce).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(res.events[0].ty, "channel_reduce_announced");
+
+        // the recipient consent is ignored, the sender still recovers its funds after the
+        // challenge period
+        let close_msg = |closes: u64| ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                SENDER_KEY,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes,
+                },
+            ),
+            channels: vec![(
+                RECIPIENT_KEY1.to_string(),
+                prover.prove(
+                    RECIPIENT_KEY1,
+                    CommitmentType::CloseConsent {
+                        sender_key: SENDER_KEY,
+                        recipient_key: RECIPIENT_KEY1,
+                        epoch: 0,
+                        withdrawn: 0,
+                    },
+                ),
+            )],
+        };
+        let res = execute(deps.as_mut(), mock_env(), operator.clone(), close_msg(0)).unwrap();
+        assert!(res.messages.is_empty());
+
+        // the challenge period does not run while the recipient can not cash
+        let now = mock_env().block.time.seconds();
+        let at = |seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            env
+        };
+        let err = execute(deps.as_mut(), at(100), operator.clone(), close_msg(1)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ChallengePeriod {
+                deadline: now + 200
+            }
+        );
+
+        // unpausing cashing lets the recipient cash during the rest of the challenge period
+        let cashing_only = PauseFlags {
+            cashing: true,
+            ..PauseFlags::default()
+        };
+        execute(
+            deps.as_mut(),
+            at(100),
+            admin,
+            ExecuteMsg::Unpause {
+                flags: cashing_only,
+            },
+        )
+        .unwrap();
+        let config: Config =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(
+            config.paused,
+            PauseFlags {
+                cashing: false,
+                ..all
+            }
+        );
+        assert_eq!(config.cashing_paused_at, None);
+        assert_eq!(config.cashing_paused_for, 100);
+        execute(deps.as_mut(), at(100), relayer, cashing).unwrap();
+
+        let err = execute(deps.as_mut(), at(150), operator.clone(), close_msg(1)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ChallengePeriod {
+                deadline: now + 200
+            }
+        );
+
+        let res = execute(deps.as_mut(), at(200), operator, close_msg(1)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(800, TEST_DENOM),
+            })
+        );
+    }
+
+    #[test]
+    fn test_close_while_cashing_paused() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let operator = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), operator.clone(), add_payment).unwrap();
+
+        let close_msg = |closes: u64, consent: Vec<u8>| ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                SENDER_KEY,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes,
+                },
+            ),
+            channels: vec![(RECIPIENT_KEY1.to_string(), consent)],
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            operator.clone(),
+            close_msg(0, vec![]),
+        )
+        .unwrap();
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            ExecuteMsg::Pause {
+                flags: PauseFlags {
+                    cashing: true,
+                    ..PauseFlags::default()
+                },
+            },
+        )
+        .unwrap();
+
+        // the deadline is pushed back for as long as cashing is paused
+        let now = mock_env().block.time.seconds();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(150);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            close_msg(1, vec![]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ChallengePeriod {
+                deadline: now + 250
+            }
+        );
+
+        // the recipient consent still settles the closing channel at once
+        let consent = prover.prove(
+            RECIPIENT_KEY1,
+            CommitmentType::CloseConsent {
+                sender_key: SENDER_KEY,
+                recipient_key: RECIPIENT_KEY1,
+                epoch: 0,
+                withdrawn: 0,
+            },
+        );
+        let res = execute(deps.as_mut(), env, operator, close_msg(1, consent)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(1000, TEST_DENOM),
+            })
+        );
+        assert_eq!(
+            res.events[0],
+            Event::new("channel_closed")
+                .add_attribute("sender_key", SENDER_KEY)
+                .add_attribute("recipient_key", RECIPIENT_KEY1)
+                .add_attribute("refund", "1000")
+                .add_attribute("cooperative", "true")
+        );
+    }
+
+    #[test]
+    fn test_settle_and_close() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("operator", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let settle = |nonce: u64| ExecuteMsg::SettleAndClose {
+            sender_key: SENDER_KEY.to_string(),
+            final_cheque: cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, nonce, None),
+        };
+
+        // only the recipient payout address can settle
+        let info = mock_info("relayer", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, settle(4)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotPayoutAddress {
+                sender: "relayer".to_string()
+            }
+        );
+
+        let msg = ExecuteMsg::SettleAndClose {
+            sender_key: "1002".to_string(),
+            final_cheque: cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 4, None),
+        };
+        let info = mock_info("recipient_addr", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ChannelNotFound);
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), settle(4)).unwrap();
+        assert_eq!(
+            res.messages
+                .into_iter()
+                .map(|msg| msg.msg)
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "recipient_addr".to_string(),
+                    amount: coins(400, TEST_DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "operator".to_string(),
+                    amount: coins(600, TEST_DENOM),
+                }),
+            ]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RecipientCount {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+            },
+        );
+        assert_eq!(from_json::<u32>(res.unwrap()).unwrap(), 0);
+
+        let err = execute(deps.as_mut(), mock_env(), info, settle(5)).unwrap_err();
+        assert_eq!(err, ContractError::ChannelSettled);
+    }
+
+    #[test]
+    fn test_reduce_channel() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let reduce = |amount: u128, commitment: Option<Vec<u8>>| ExecuteMsg::ReduceChannel {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            amount,
+            commitment,
+        };
+        let consent = |max_amount: u128, amount: u128, reductions: u64| {
+            prover.prove(
+                RECIPIENT_KEY1,
+                CommitmentType::ReduceChannel {
+                    sender_key: SENDER_KEY,
+                    recipient_key: RECIPIENT_KEY1,
+                    max_amount,
+                    amount,
+                    reductions,
+                },
+            )
+        };
+        let recipient = |deps: Deps| {
+            let res = query(
+                deps,
+                mock_env(),
+                QueryMsg::PaymentChan {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                    recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                    start_after: None,
+                    limit: None,
+                },
+            );
+            from_json::<Vec<RecipientInfo>>(res.unwrap()).unwrap()[0]
+                .recipient
+                .clone()
+        };
+        let refund = |amount: u128| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".to_string(),
+                amount: coins(amount, TEST_DENOM),
+            })
+        };
+
+        let operator = mock_info("sender", &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("someone", &[]),
+            reduce(400, None),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOperator {
+                sender: "someone".to_string(),
+                operator: "sender".to_string(),
+            }
+        );
+        for amount in [0, 1001] {
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                operator.clone(),
+                reduce(amount, None),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::InvalidReduceAmount);
+        }
+
+        // without consent the reduction waits for the notice period
+        let env = mock_env();
+        let available_at = env.block.time.seconds() + 100;
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(400, None),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            recipient(deps.as_ref()).reduction,
+            Some(Reduction {
+                amount: 400,
+                available_at,
+                paused_for: 0,
+            })
+        );
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(400, None),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoticePeriod { available_at });
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(400, None),
+        )
+        .unwrap();
+        assert_eq!(res.messages[0].msg, refund(400));
+        let reduced = recipient(deps.as_ref());
+        assert_eq!(reduced.max_amount, 600);
+        assert_eq!(reduced.reduction, None);
+
+        // cheques cashed during the notice period take precedence
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(300, None),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 4, None)],
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("relayer", &[]), msg).unwrap();
+        env.block.time = env.block.time.plus_seconds(100);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(300, None),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidReduceAmount);
+
+        // the recipient consent applies at once
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(100, Some(consent(1000, 100, 0))),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            operator.clone(),
+            reduce(100, Some(consent(600, 100, 1))),
+        )
+        .unwrap();
+        assert_eq!(res.messages[0].msg, refund(100));
+        let reduced = recipient(deps.as_ref());
+        assert_eq!(reduced.max_amount, 500);
+        assert_eq!(reduced.remain().unwrap(), 100);
+        assert_eq!(reduced.reduction, None);
+
+        // a consent is used up even when a top up restores the balance it was signed for
+        let top_up = ExecuteMsg::TopUp {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+        };
+        let info = mock_info("sender", &coins(100, TEST_DENOM));
+        execute(deps.as_mut(), env.clone(), info, top_up).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env,
+            operator,
+            reduce(100, Some(consent(600, 100, 1))),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+    }
+
+    #[test]
+    fn test_add_signer() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        // face_value = 100; total_amount = 10000;
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "sender".to_string(),
+            amount: Uint128::new(10000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+
+        info.sender = Addr::unchecked("cw20_contract_addr");
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert_eq!(res.attributes.len(), 1);
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3009".to_string()],
+        };
+
+        info.sender = Addr::unchecked("sender");
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), add_signer.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOperator {
+                sender: "sender".to_string(),
+                operator: "operator".to_string(),
+            }
+        );
+
+        info.sender = Addr::unchecked("operator");
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), add_signer).unwrap();
         assert_eq!(res.attributes.len(), 1);
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["new_signer".to_string()],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), add_signer).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidKey {
+                key: "new_signer".to_string()
+            }
+        );
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY2.to_string(),
+            signers: vec!["3009".to_string()],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), add_signer).unwrap_err();
+        assert_eq!(err, ContractError::RecipientNotFound);
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: "1002".to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3009".to_string()],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, add_signer).unwrap_err();
+        assert_eq!(err, ContractError::ChannelNotFound);
+    }
+
+    #[test]
+    fn test_transfer_operator() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("funder", &coins(1000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let transfer = |new_operator: &str| ExecuteMsg::TransferOperator {
+            chan_key: SENDER_KEY.to_string(),
+            new_operator: new_operator.to_string(),
+        };
+        let accept = ExecuteMsg::AcceptOperator {
+            chan_key: SENDER_KEY.to_string(),
+        };
+
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, transfer("new_operator")).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOperator {
+                sender: "someone".to_string(),
+                operator: "operator".to_string(),
+            }
+        );
+
+        let operator = mock_info("operator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), operator.clone(), transfer("")).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            operator.clone(),
+            transfer("new_operator"),
+        )
+        .unwrap();
+        assert_eq!(res.events[0].ty, "operator_transfer_proposed");
+
+        // the current operator stays in charge until the proposed one accepts
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, accept.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotPendingOperator {
+                sender: "someone".to_string()
+            }
+        );
+
+        let new_operator = mock_info("new_operator", &[]);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            new_operator.clone(),
+            accept.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.events,
+            vec![Event::new("operator_transferred")
+                .add_attribute("sender_key", SENDER_KEY)
+                .add_attribute("old_operator", "operator")
+                .add_attribute("operator", "new_operator")]
+        );
+
+        let err = execute(deps.as_mut(), mock_env(), new_operator.clone(), accept).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotPendingOperator {
+                sender: "new_operator".to_string()
+            }
+        );
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3001".to_string()],
+        };
+        let err = execute(deps.as_mut(), mock_env(), operator, add_signer.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotOperator {
+                sender: "operator".to_string(),
+                operator: "new_operator".to_string(),
+            }
+        );
+        execute(deps.as_mut(), mock_env(), new_operator, add_signer).unwrap();
+    }
+
+    #[test]
+    fn test_remove_signer() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec!["3001".to_string(), "3001".to_string()],
+            }],
+        };
+        let operator = mock_info("sender", &[]);
+        let info = mock_info("sender", &coins(10000, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+
+        let add_signer = |signers: Vec<String>| ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers,
+        };
+        let remove_signer = |signer: &str, nonce: Option<u64>| ExecuteMsg::RemoveSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signer: signer.to_string(),
+            nonce,
+        };
+        let cash = |deps: DepsMut, signer_key: &str, nonce: u64| {
+            let msg = ExecuteMsg::Cashing {
+                recipient_key: RECIPIENT_KEY1.to_string(),
+                cheques: vec![delegated_cheque(&prover, signer_key, nonce)],
+            };
+            execute(deps, mock_env(), mock_info("relayer", &[]), msg)
+        };
+        let recipient = |deps: Deps| {
+            let res = query(
+                deps,
+                mock_env(),
+                QueryMsg::PaymentChan {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                    recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                    start_after: None,
+                    limit: None,
+                },
+            );
+            from_json::<Vec<RecipientInfo>>(res.unwrap()).unwrap()[0]
+                .recipient
+                .clone()
+        };
+        let signers = |deps: Deps| recipient(deps).approve_signers;
+
+        // signers are deduplicated and bounded
+        let msg = add_signer(vec![
+            "3001".to_string(),
+            "3002".to_string(),
+            "3002".to_string(),
+        ]);
+        execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap();
+        assert_eq!(signers(deps.as_ref()), vec!["3001", "3002"]);
+
+        let msg = add_signer((0..15).map(|i| (4000 + i).to_string()).collect());
+        let err = execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::ExceedSignerNum);
+
+        cash(deps.as_mut(), "3001", 2).unwrap();
+
+        // cheques of a revoked signer stay valid up to the revocation nonce
+        let msg = remove_signer("3001", Some(4));
+        execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap();
+        assert_eq!(signers(deps.as_ref()), vec!["3002"]);
+        cash(deps.as_mut(), "3001", 3).unwrap();
+        let err = cash(deps.as_mut(), "3001", 5).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnapprovedSigner {
+                signer_key: "3001".to_string()
+            }
+        );
+
+        let msg = remove_signer("3001", None);
+        let err = execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::SignerNotFound {
+                signer_key: "3001".to_string()
+            }
+        );
+
+        // dropped signers are revoked at the withdrawn nonce by default
+        let msg = ExecuteMsg::ReplaceSigners {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3003".to_string(), "3003".to_string()],
+            nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap();
+        assert_eq!(signers(deps.as_ref()), vec!["3003"]);
+        let err = cash(deps.as_mut(), "3002", 4).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnapprovedSigner {
+                signer_key: "3002".to_string()
+            }
+        );
+        cash(deps.as_mut(), "3003", 4).unwrap();
+
+        // revocations without cheques left to cash are only kept to block the signers
+        let r = recipient(deps.as_ref());
+        assert!(r.revoked_signers.is_empty());
+        assert_eq!(r.blocked_signers, vec!["3002", "3001"]);
+
+        // funding the channel leaves revoked signers revoked
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 100,
+                approve_signers: vec!["3001".to_string()],
+            }],
+        };
+        let info = mock_info("sender", &coins(100, TEST_DENOM));
+        execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+        assert_eq!(signers(deps.as_ref()), vec!["3003"]);
+        assert!(cash(deps.as_mut(), "3001", 6).is_err());
+
+        // approving a revoked signer again lifts its revocation
+        let msg = add_signer(vec!["3001".to_string()]);
+        execute(deps.as_mut(), mock_env(), operator.clone(), msg).unwrap();
+        cash(deps.as_mut(), "3001", 6).unwrap();
+        assert_eq!(recipient(deps.as_ref()).blocked_signers, vec!["3002"]);
+
+        // revocations are bounded as well
+        let replace = |round: u64| ExecuteMsg::ReplaceSigners {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: (0..16)
+                .map(|i| (5000 + round * 16 + i).to_string())
+                .collect(),
+            nonce: None,
+        };
+        for round in 0..4 {
+            execute(deps.as_mut(), mock_env(), operator.clone(), replace(round)).unwrap();
+        }
+        let err = execute(deps.as_mut(), mock_env(), operator, replace(4)).unwrap_err();
+        assert_eq!(err, ContractError::ExceedRevokedSignerNum);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Native(TEST_DENOM.to_string()),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+
+        let channel_event = |ty: &str| {
+            Event::new(ty)
+                .add_attribute("sender_key", SENDER_KEY)
+                .add_attribute("recipient_key", RECIPIENT_KEY1)
+        };
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: None,
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 1000,
+                approve_signers: vec![],
+            }],
+        };
+        let info = mock_info("sender", &coins(1000, TEST_DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), add_payment.clone()).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("channel_opened")
+                .add_attribute("amount", "1000")
+                .add_attribute("max_amount", "1000")
+                .add_attribute("face_value", "100")
+                .add_attribute("operator", "sender")]
+        );
+
+        let res = execute(deps.as_mut(), mock_env(), info, add_payment).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("channel_topped_up")
+                .add_attribute("amount", "1000")
+                .add_attribute("max_amount", "2000")
+                .add_attribute("face_value", "100")
+                .add_attribute("operator", "sender")]
+        );
+
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            signers: vec!["3001".to_string(), "3002".to_string()],
+        };
+        let operator = mock_info("sender", &[]);
+        let res = execute(deps.as_mut(), mock_env(), operator.clone(), add_signer).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("signer_added").add_attribute("signers", "3001,3002")]
+        );
+
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 3, None)],
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("cheque_cashed")
+                .add_attribute("nonce", "3")
+                .add_attribute("amount", "300")
+                .add_attribute("withdrawn", "300")]
+        );
+
+        let close_msg = |closes: u64| ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(
+                SENDER_KEY,
+                CommitmentType::CloseChannel {
+                    sender_key: SENDER_KEY,
+                    closes,
+                },
+            ),
+            channels: vec![(RECIPIENT_KEY1.to_string(), vec![])],
+        };
+        let env = mock_env();
+        let deadline = env.block.time.seconds() + 100;
+        let res = execute(deps.as_mut(), env, operator.clone(), close_msg(0)).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("channel_closing").add_attribute("deadline", deadline.to_string())]
+        );
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(deps.as_mut(), env, operator, close_msg(1)).unwrap();
+        assert_eq!(
+            res.events,
+            vec![channel_event("channel_closed")
+                .add_attribute("refund", "1700")
+                .add_attribute("cooperative", "false")]
+        );
+
+        let msg = ExecuteMsg::UpdateConfig {
+            auto_release_time: Some(200),
+            max_recipient: None,
+            fee: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        assert_eq!(
+            res.events,
+            vec![Event::new("config_updated").add_attribute("auto_release_time", "200")]
+        );
+    }
+
+    #[test]
+    fn test_cw20_receive_errors() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: prover.verifying_key(),
+        };
+
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: None,
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+        let receive = |msg: &ExecuteMsg| {
+            ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+                sender: "operator".to_string(),
+                amount: Uint128::new(10000),
+                msg: to_json_binary(msg).unwrap(),
+            })
+        };
+
+        let info = mock_info("other_cw20_contract_addr", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, receive(&add_payment)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnauthorizedCw20 {
+                sender: "other_cw20_contract_addr".to_string()
+            }
+        );
+
+        // accepting a native denom does not accept a cw20 contract of the same name
+        let msg = ExecuteMsg::UpdateDenoms {
+            add: vec![Denom::Native("other_cw20_contract_addr".to_string())],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        let info = mock_info("other_cw20_contract_addr", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, receive(&add_payment)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnauthorizedCw20 {
+                sender: "other_cw20_contract_addr".to_string()
+            }
+        );
+
+        let info = mock_info("cw20_contract_addr", &[]);
+        let cashing = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, receive(&cashing)).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportMsg);
+    }
+
+    #[test]
+    fn test_migrate_payment_channels() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+
+        // config layout of the first release, which stored no verifying key
+        #[derive(serde::Serialize)]
+        struct BaselineConfig {
+            denom: Denom,
+            auto_release_time: u64,
+            owner: CanonicalAddr,
+            max_recipient: u32,
+        }
+        let baseline = BaselineConfig {
+            denom: Denom::Native(TEST_DENOM.to_string()),
+            auto_release_time: 100,
+            owner: deps.api.addr_canonicalize("admin").unwrap(),
+            max_recipient: 1024,
+        };
+        deps.storage
+            .set(CONFIG.as_slice(), &to_json_vec(&baseline).unwrap());
+        set_contract_version(deps.as_mut().storage, "crates.io:micro_payment", "0.0.1").unwrap();
+
+        let legacy = LegacyPaymentChannel {
+            operator: "operator".to_string(),
+            recipients: HashMap::from([
+                (
+                    RECIPIENT_KEY1.to_string(),
+                    LegacyRecipient {
+                        max_amount: 10000,
+                        nonce_withdrawl: Some(3),
+                        face_value: Some(100),
+                        withdrawn_amount: None,
+                        auto_release: None,
+                        approve_signers: vec![],
+                    },
+                ),
+                (
+                    RECIPIENT_KEY2.to_string(),
+                    LegacyRecipient {
+                        max_amount: 20000,
+                        nonce_withdrawl: None,
+                        face_value: Some(200),
+                        withdrawn_amount: None,
+                        auto_release: Some(5000),
+                        approve_signers: vec![],
+                    },
+                ),
+            ]),
+        };
+        LEGACY_PAYMENT_CHANNELS
+            .save(deps.as_mut().storage, SENDER_KEY.to_string(), &legacy)
+            .unwrap();
+        // channels of a non canonical sender key can only be recovered through reductions
+        LEGACY_PAYMENT_CHANNELS
+            .save(
+                deps.as_mut().storage,
+                "sender_pubkey_hash".to_string(),
+                &LegacyPaymentChannel {
+                    operator: "operator".to_string(),
+                    recipients: HashMap::from([(
+                        RECIPIENT_KEY1.to_string(),
+                        LegacyRecipient {
+                            max_amount: 10000,
+                            nonce_withdrawl: None,
+                            face_value: Some(100),
+                            withdrawn_amount: None,
+                            auto_release: Some(5000),
+                            approve_signers: vec![],
+                        },
+                    )]),
+                },
+            )
+            .unwrap();
+
+        let err = migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg {
+                verifying_key: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::MissingVerifyingKey);
+        let msg = MigrateMsg {
+            verifying_key: Some(prover.verifying_key()),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: SENDER_KEY.to_string(),
+                recipient_pubkey_hash: None,
+                start_after: None,
+                limit: None,
+            },
+        );
+        let recipients: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        assert_eq!(
+            recipients,
+            vec![
+                RecipientInfo {
+                    key: RECIPIENT_KEY1.to_string(),
+                    recipient: Recipient {
+                        nonce_withdrawl: Some(3),
+                        ..Recipient::new(vec![], 10000, Some(100))
+                    },
+                },
+                RecipientInfo {
+                    key: RECIPIENT_KEY2.to_string(),
+                    // a pending auto release becomes a challenge period
+                    recipient: Recipient {
+                        state: ChannelState::Closing {
+                            deadline: 5000,
+                            paused_for: 0,
+                        },
+                        ..Recipient::new(vec![], 20000, Some(200))
+                    },
+                },
+            ]
+        );
+
+        let count: u32 = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::RecipientCount {
+                    sender_pubkey_hash: SENDER_KEY.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PaymentChan {
+                sender_pubkey_hash: "sender_pubkey_hash".to_string(),
+                recipient_pubkey_hash: Some(RECIPIENT_KEY1.to_string()),
+                start_after: None,
+                limit: None,
+            },
+        );
+        let recipients: Vec<RecipientInfo> = from_json(res.unwrap()).unwrap();
+        assert_eq!(recipients[0].recipient.state, ChannelState::Open);
+
+        // the operator is carried over
+        let add_signer = ExecuteMsg::AddSigner {
+            chan_key: SENDER_KEY.to_string(),
+            recipient_key: RECIPIENT_KEY2.to_string(),
+            signers: vec!["3009".to_string()],
+        };
+        let info = mock_info("operator", &[]);
+        execute(deps.as_mut(), mock_env(), info, add_signer).unwrap();
+
+        assert!(LEGACY_PAYMENT_CHANNELS
+            .may_load(deps.as_ref().storage, SENDER_KEY.to_string())
+            .unwrap()
+            .is_none());
+
+        // commitments verify against the key stored by the migration
+        register_payout(deps.as_mut(), &prover, RECIPIENT_KEY1, "recipient_addr");
+        let msg = ExecuteMsg::Cashing {
+            recipient_key: RECIPIENT_KEY1.to_string(),
+            cheques: vec![cheque(&prover, SENDER_KEY, RECIPIENT_KEY1, 4, None)],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn test_update_verifying_key() {
+        let mut deps = mock_dependencies();
+        let prover = MockProver::new();
+        let new_prover = MockProver::with_seed(1);
+
+        let msg = InstantiateMsg {
+            denom: crate::state::Denom::Cw20(Addr::unchecked("cw20_contract_addr")),
+            admin: Some("admin".to_string()),
+            auto_release_time: 100,
+            max_recipient: 1024,
+            verifying_key: Binary::from(vec![1, 2, 3]),
+        };
+
+        let mut info = mock_info("admin", &coins(0, TEST_DENOM.to_string()));
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::InvalidVerifyingKey);
+
+        let msg = InstantiateMsg {
+            verifying_key: prover.verifying_key(),
+            ..msg
+        };
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let update_vk = ExecuteMsg::UpdateVerifyingKey {
+            verifying_key: new_prover.verifying_key(),
+            grace_period: Some(100),
+        };
+
+        info.sender = Addr::unchecked("not_admin");
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), update_vk.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotOwner { .. }));
+
+        info.sender = Addr::unchecked("admin");
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), update_vk).unwrap();
+        assert_eq!(res.attributes.len(), 2);
+
+        let vk_res: VerifyingKeyResponse =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::VerifyingKey {}).unwrap())
+                .unwrap();
+        assert_eq!(vk_res.current.id, 2);
+        assert_eq!(vk_res.current.key, new_prover.verifying_key());
+        assert_eq!(vk_res.retired.len(), 1);
+        assert_eq!(vk_res.retired[0].id, 1);
+        assert_eq!(
+            vk_res.retired[0].expires_at,
+            Some(mock_env().block.time.seconds() + 100)
+        );
+
+        let add_payment = ExecuteMsg::AddPaymentChan {
+            operator: Some("operator".to_string()),
+            chan_key: SENDER_KEY.to_string(),
+            channels: vec![Channel {
+                key: RECIPIENT_KEY1.to_string(),
+                face_value: Some(100),
+                max_amount: 10000,
+                approve_signers: vec![],
+            }],
+        };
+
+        let msg = ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "sender".to_string(),
+            amount: Uint128::new(10000),
+            msg: to_json_binary(&add_payment).unwrap(),
+        });
+
+        info.sender = Addr::unchecked("cw20_contract_addr");
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let close_commitment = CommitmentType::CloseChannel {
+            sender_key: SENDER_KEY,
+            closes: 0,
+        };
+        let close_msg = ExecuteMsg::ClosePaymentChan {
+            chan_key: SENDER_KEY.to_string(),
+            commitment: prover.prove(SENDER_KEY, close_commitment),
+            channels: vec![],
+        };
+
+        // proofs of the retired key are rejected once the grace window is over
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        info.sender = Addr::unchecked("operator");
+        let err = execute(deps.as_mut(), env, info.clone(), close_msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::ChecksVerifyFailed);
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let vk_res: VerifyingKeyResponse =
+            from_json(query(deps.as_ref(), env, QueryMsg::VerifyingKey {}).unwrap()).unwrap();
+        assert!(vk_res.retired.is_empty());
+
+        // and accepted within it
+        execute(deps.as_mut(), mock_env(), info, close_msg).unwrap();
     }
 }
@@ -1,33 +1,61 @@
-use ark_bn254::Bn254;
-use ark_circom::{CircomBuilder, CircomConfig};
-use ark_groth16::Groth16;
-use ark_std::rand::thread_rng;
-use color_eyre::Result;
-use lazy_static::lazy_static;
-use num_bigint::BigInt;
-
-type _GrothBn = Groth16<Bn254>;
-
-lazy_static! {
-    pub static ref CIRCOM_CONFIG: CircomConfig<ark_ec::bn::Bn<ark_bn254::Config>> =
-        CircomConfig::<Bn254>::new("./zkp_files/circuit.wasm", "./zkp_files/circuit.r1cs").unwrap();
+use ark_bn254::{Bn254, Fr};
+use ark_ff::PrimeField;
+use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use std::str::FromStr;
+use tiny_keccak::{Hasher, Keccak};
+
+type GrothBn = Groth16<Bn254>;
+
+/// Number of public inputs of `payment.circom`
+const PUBLIC_INPUTS: usize = 2;
+
+/// Parse a pubkey hash key into a field element.
+///
+/// Keys are the decimal representation of `Poseidon(Ax, Ay)` as output by the circuit.
+/// Non canonical representations (leading zeroes, values >= modulus) are rejected so that
+/// one pubkey hash can never be addressed by two different keys.
+pub fn key_to_field(key: &str) -> Option<Fr> {
+    Fr::from_str(key).ok().filter(|f| f.to_string() == key)
 }
 
-#[warn(dead_code)]
-pub fn _verify_proof(_pub_inputs: Vec<(impl ToString, impl Into<BigInt>)>) -> Result<()> {
-    let mut _builder = CircomBuilder::new(CIRCOM_CONFIG.to_owned());
+/// Map the commitment preimage onto the `msg` public input of the circuit.
+pub fn msg_to_field(msg: &[u8]) -> Fr {
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+    hasher.update(msg);
+    hasher.finalize(&mut hash);
 
-    let circom = _builder.setup();
+    Fr::from_be_bytes_mod_order(&hash)
+}
 
-    let mut rng = thread_rng();
-    let _params = _GrothBn::generate_random_parameters_with_reduction(circom, &mut rng)?;
+/// Public inputs of `payment.circom` in declaration order: `[msg, sender_pubkey_hash]`.
+pub fn public_inputs(signer_key: &str, msg: &[u8]) -> Option<[Fr; PUBLIC_INPUTS]> {
+    Some([msg_to_field(msg), key_to_field(signer_key)?])
+}
 
-    let _circom: ark_circom::CircomCircuit<ark_ec::bn::Bn<ark_bn254::Config>> = _builder.build()?;
+/// Check `vk` is a compressed Groth16 verifying key over BN254 matching the circuit public inputs
+pub fn is_valid_verifying_key(vk: &[u8]) -> bool {
+    VerifyingKey::<Bn254>::deserialize_compressed(vk)
+        .is_ok_and(|vk| vk.gamma_abc_g1.len() == PUBLIC_INPUTS + 1)
+}
 
-    Ok(())
+/// Verify a compressed Groth16 proof over BN254 against a compressed verifying key.
+pub fn verify_proof(vk: &[u8], proof: &[u8], public_inputs: &[Fr]) -> bool {
+    let Ok(vk) = VerifyingKey::<Bn254>::deserialize_compressed(vk) else {
+        return false;
+    };
+    let Ok(proof) = Proof::<Bn254>::deserialize_compressed(proof) else {
+        return false;
+    };
+
+    let pvk = prepare_verifying_key(&vk);
+    GrothBn::verify_proof(&pvk, &proof, public_inputs).unwrap_or(false)
 }
 
 #[test]
-fn test_circom_config() {
-    println!("{}", CIRCOM_CONFIG.r1cs.constraints.len());
+fn test_key_to_field() {
+    assert!(key_to_field("1024").is_some());
+    assert!(key_to_field("01024").is_none());
+    assert!(key_to_field("pubkey_hash").is_none());
 }